@@ -2,17 +2,25 @@ use std::sync::Mutex;
 
 use gilrs::{GamepadId, Gilrs, GilrsBuilder};
 use glam::{vec2, Vec2};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ControlState {
   pub movement: Vec2,
   pub jump: bool,
   pub swing: bool,
 
   pub reset: bool,
+  /// Held to run time backwards.
+  pub rewind: bool,
 }
 
 impl ControlState {
+  /// Read the keyboard and gamepad.
+  ///
+  /// Only [`InputSource`](crate::replay::InputSource) should call this, so
+  /// everything else sees the same controls no matter where they came from.
   pub fn calculate() -> Self {
     let base = Self::calculate_from_kb();
 
@@ -48,12 +56,14 @@ impl ControlState {
     let swing = is_key_down(KeyCode::J);
 
     let reset = is_key_down(KeyCode::R);
+    let rewind = is_key_down(KeyCode::Backspace);
 
     Self {
       movement,
       jump,
       swing,
       reset,
+      rewind,
     }
   }
 
@@ -62,12 +72,14 @@ impl ControlState {
     let jump = self.jump || other.jump;
     let swing = self.swing || other.swing;
     let reset = self.reset || other.reset;
+    let rewind = self.rewind || other.rewind;
 
     ControlState {
       movement,
       jump,
       swing,
       reset,
+      rewind,
     }
   }
 }
@@ -145,12 +157,14 @@ impl GilrsState {
     let swing = gp.value(Axis::RightZ) >= Self::TRIGGER_DEPTH;
 
     let reset = gp.is_pressed(Button::Start);
+    let rewind = gp.value(Axis::LeftZ) >= Self::TRIGGER_DEPTH;
 
     Some(ControlState {
       movement,
       jump,
       swing,
       reset,
+      rewind,
     })
   }
 }
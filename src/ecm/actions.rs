@@ -1,15 +1,67 @@
+use aglet::CoordVec;
 use palkia::prelude::*;
 
-use super::component::Collider;
+use super::{
+    component::{
+        Collider, HasDims, Mover, PlayerController, Positioned, Slope,
+    },
+    resource::TheControls,
+};
+use crate::geom::Hitbox;
 
-pub fn collides_with<A: AccessQuery>(
+/// Whether `src` gets stopped by `dst` when it takes a step of `motion`,
+/// ending up at `next`.
+///
+/// `prev` is where `src` was before this step, which one-way [`Collider`]s
+/// need to know what side it's coming from. [`Slope`]s only care about where
+/// it ends up.
+pub fn collides_with<A: AccessQuery + AccessResources>(
     access: &A,
     src: Entity,
     dst: Entity,
+    motion: CoordVec,
+    prev: Hitbox,
+    next: Hitbox,
 ) -> bool {
     if src == dst {
         return false;
     }
 
-    access.query::<&Collider>(dst).is_some()
+    let Some(collider) = access.query::<&Collider>(dst) else {
+        return false;
+    };
+    if !access
+        .query::<&Mover>(src)
+        .is_none_or(|mover| collider.blocks(&mover))
+    {
+        return false;
+    }
+    if collider.can_drop_through() && wants_to_drop(access, src) {
+        return false;
+    }
+    if !collider.blocks_step(motion, prev, hitbox_of(access, dst)) {
+        return false;
+    }
+    match access.query::<&Slope>(dst) {
+        Some(slope) => slope.overlaps(hitbox_of(access, dst), next),
+        None => true,
+    }
+}
+
+fn hitbox_of<A: AccessQuery>(access: &A, e: Entity) -> Hitbox {
+    let (pos, dims) = access.query::<(&Positioned, &HasDims)>(e).unwrap();
+    pos.make_hitbox(*dims)
+}
+
+/// Whether it's holding down to drop through one-way colliders. Only players
+/// can, and they're steered by [`TheControls`], which are this tick's
+/// controls even right after a snapshot's been restored.
+fn wants_to_drop<A: AccessQuery + AccessResources>(
+    access: &A,
+    e: Entity,
+) -> bool {
+    access.query::<&PlayerController>(e).is_some()
+        && access
+            .read_resource::<TheControls>()
+            .is_ok_and(|controls| controls.0.movement.y > 0.0)
 }
@@ -0,0 +1,33 @@
+use palkia::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ecm::{
+  component::Positioned,
+  message::{MsgTrigger, TriggerPhase},
+  resource::{RespawnPoint, ThePlayerEntity},
+};
+
+/// When the player enters this [`Trigger`], they respawn here from then on.
+///
+/// [`Trigger`]: crate::ecm::component::Trigger
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint;
+
+impl Component for Checkpoint {
+  fn register_handlers(builder: HandlerBuilder<Self>) -> HandlerBuilder<Self>
+  where
+    Self: Sized,
+  {
+    builder.handle_read(|_, msg: MsgTrigger, me, access| {
+      let player = access.read_resource::<ThePlayerEntity>().unwrap().0;
+      if msg.phase() == TriggerPhase::Enter
+        && msg.trigger() == me
+        && msg.visitor() == player
+      {
+        let pos = access.query::<&Positioned>(me).unwrap().pos;
+        access.write_resource::<RespawnPoint>().unwrap().0 = pos;
+      }
+      msg
+    })
+  }
+}
@@ -7,12 +7,19 @@ use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
 
 use crate::{
-  ecm::{message::MsgDraw, resource::Camera},
+  ecm::{
+    message::{MsgDraw, MsgTick},
+    resource::{Camera, FabCtxHolder, HitboxTracker, TreeHolder},
+  },
+  fabctx::FabCtx,
+  geom::Hitbox,
   gfx::{de_hexcol, ser_hexcol},
   resources::Resources,
+  sprite::{Frame, SpriteSheet},
+  tileset::Tileset,
 };
 
-use super::{HasDims, Positioned};
+use super::{Collider, HasDims, Positioned};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(transparent)]
@@ -22,12 +29,6 @@ pub struct ColoredHitbox {
   color: mq::Color,
 }
 
-impl ColoredHitbox {
-  pub fn new(color: mq::Color) -> Self {
-    Self { color }
-  }
-}
-
 impl Component for ColoredHitbox {
   fn register_handlers(builder: HandlerBuilder<Self>) -> HandlerBuilder<Self>
   where
@@ -38,8 +39,9 @@ impl Component for ColoredHitbox {
       let dims = access.query::<&HasDims>(me).unwrap();
       let cam = access.read_resource::<Camera>().unwrap();
 
-      let corner =
-        pos.pos - CoordVec::new(dims.w / 2, dims.h / 2) - cam.center();
+      let corner = pos.pos_at(msg.alpha())
+        - CoordVec::new(dims.w / 2, dims.h / 2)
+        - cam.center_at(msg.alpha());
       mq::draw_rectangle(
         corner.x as f32,
         corner.y as f32,
@@ -69,17 +71,301 @@ impl Component for DrawTexture {
       let dims = access.query::<&HasDims>(me).unwrap();
       let cam = access.read_resource::<Camera>().unwrap();
 
-      let corner =
-        pos.pos - CoordVec::new(dims.w / 2, dims.h / 2) - cam.center();
+      let corner = pos.pos_at(msg.alpha())
+        - CoordVec::new(dims.w / 2, dims.h / 2)
+        - cam.center_at(msg.alpha());
       let assets = Resources::get();
       let tex = assets.get_texture(&this.tex);
-      mq::draw_texture(tex, corner.x as f32, corner.y as f32, mq::WHITE);
+      mq::draw_texture_ex(
+        tex,
+        corner.x as f32,
+        corner.y as f32,
+        mq::WHITE,
+        mq::DrawTextureParams {
+          dest_size: Some(mq::vec2(dims.w as f32, dims.h as f32)),
+          ..Default::default()
+        },
+      );
+
+      msg
+    })
+  }
+}
+
+/// Draws a tile from an autotile, picked by which neighboring entities are
+/// solid.
+///
+/// The neighbors are checked once the whole level has been spawned, by
+/// [`AutotileSprite::resolve_all`], so this is only good for things that don't
+/// move. Until then, it draws the autotile's first tile.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AutotileSprite {
+  tileset: SmolStr,
+  autotile: SmolStr,
+  #[serde(default)]
+  picked: Option<u16>,
+}
+
+impl AutotileSprite {
+  /// Pick the tile for every autotile sprite in the world, by which static
+  /// colliders are next to it.
+  pub fn resolve_all(world: &World, ctx: &FabCtx) {
+    let mut trees = world.write_resource::<TreeHolder>().unwrap();
+    trees.rebuild_statics(&world.read_resource::<HitboxTracker>().unwrap());
+
+    for e in world.entities() {
+      let Some(mut this) = world.query::<&mut AutotileSprite>(e) else {
+        continue;
+      };
+      let Some(tileset) = ctx.tileset(&this.tileset) else {
+        continue;
+      };
+      let (pos, dims) = world.query::<(&Positioned, &HasDims)>(e).unwrap();
+      this.picked = this.pick(tileset, pos.make_hitbox(*dims), |neighbor| {
+        !trees
+          .get_entities_in_box(neighbor, |other| {
+            other != e && world.query::<&Collider>(other).is_some()
+          })
+          .is_empty()
+      });
+    }
+  }
+
+  fn pick(
+    &self,
+    tileset: &Tileset,
+    hitbox: Hitbox,
+    mut is_solid: impl FnMut(Hitbox) -> bool,
+  ) -> Option<u16> {
+    let autotile = tileset.autotile(&self.autotile)?;
+    Some(autotile.pick(|dir| {
+      let center = hitbox.center()
+        + CoordVec::new(
+          dir.deltas().x * hitbox.w(),
+          dir.deltas().y * hitbox.h(),
+        );
+      is_solid(Hitbox::new(center.x, center.y, hitbox.w(), hitbox.h()))
+    }))
+  }
+}
+
+impl Component for AutotileSprite {
+  fn register_handlers(builder: HandlerBuilder<Self>) -> HandlerBuilder<Self>
+  where
+    Self: Sized,
+  {
+    builder.handle_read(|this, msg: MsgDraw, me, access| {
+      let pos = access.query::<&Positioned>(me).unwrap();
+      let dims = access.query::<&HasDims>(me).unwrap();
+      let cam = access.read_resource::<Camera>().unwrap();
+
+      let assets = Resources::get();
+      let Some(tileset) = assets.get_tileset(&this.tileset) else {
+        return msg;
+      };
+      let Some(idx) = this.picked.or_else(|| tileset.index_of(&this.autotile))
+      else {
+        return msg;
+      };
+
+      let corner = pos.pos_at(msg.alpha())
+        - CoordVec::new(dims.w / 2, dims.h / 2)
+        - cam.center_at(msg.alpha());
+      let tex = assets.get_texture(tileset.texture());
+      mq::draw_texture_ex(
+        tex,
+        corner.x as f32,
+        corner.y as f32,
+        mq::WHITE,
+        mq::DrawTextureParams {
+          dest_size: Some(mq::vec2(dims.w as f32, dims.h as f32)),
+          source: Some(tileset.source_rect(idx, tex.width())),
+          ..Default::default()
+        },
+      );
 
       msg
     })
   }
 }
 
+/// Plays animations from a [`SpriteSheet`].
+///
+/// Frames are centered horizontally on the entity and sit on the bottom of its
+/// hitbox, so they can be bigger than the hitbox. Sprites should face right;
+/// `flip_x` makes them face left.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AnimatedSprite {
+  sheet: SmolStr,
+  anim: SmolStr,
+  #[serde(default)]
+  flip_x: bool,
+
+  /// Index into the animation's frame order.
+  #[serde(default)]
+  frame: usize,
+  /// Ticks spent on the current frame.
+  #[serde(default)]
+  timer: u32,
+  /// How many times the animation has played all the way through.
+  #[serde(default)]
+  plays: u32,
+  /// The last animation, fading out on top of this one.
+  #[serde(default)]
+  blend: Option<Blend>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Blend {
+  anim: SmolStr,
+  /// The frame it was on when it started fading. It stays on that frame.
+  frame: usize,
+  /// Ticks since it started fading.
+  timer: u32,
+  /// How many ticks it takes to fade out.
+  ticks: u32,
+}
+
+impl AnimatedSprite {
+  /// Switch to the given animation, unless it's already playing, fading the
+  /// old one out over the given number of ticks.
+  pub fn play_blended(&mut self, anim: &str, ticks: u32) {
+    if self.anim != anim {
+      let old = std::mem::replace(&mut self.anim, anim.into());
+      self.blend = (ticks > 0).then_some(Blend {
+        anim: old,
+        frame: self.frame,
+        timer: 0,
+        ticks,
+      });
+      self.frame = 0;
+      self.timer = 0;
+      self.plays = 0;
+    }
+  }
+
+  pub fn set_flip_x(&mut self, flip_x: bool) {
+    self.flip_x = flip_x;
+  }
+
+  fn advance(&mut self, sheet: &SpriteSheet) {
+    if let Some(blend) = &mut self.blend {
+      blend.timer += 1;
+      if blend.timer >= blend.ticks {
+        self.blend = None;
+      }
+    }
+
+    let Some(anim) = sheet.anim(&self.anim) else {
+      return;
+    };
+    let done = |plays| anim.repeat().is_some_and(|repeat| plays >= repeat);
+    if done(self.plays) {
+      return;
+    }
+
+    self.timer += 1;
+    let Some(frame) = sheet.anim_frame(&self.anim, self.frame) else {
+      // Must be from some other version of the sheet
+      self.frame = 0;
+      self.timer = 0;
+      return;
+    };
+    if self.timer < frame.duration {
+      return;
+    }
+    self.timer = 0;
+    if self.frame + 1 < anim.frames().len() {
+      self.frame += 1;
+    } else {
+      self.plays += 1;
+      if !done(self.plays) {
+        self.frame = 0;
+      }
+    }
+  }
+}
+
+impl Component for AnimatedSprite {
+  fn register_handlers(builder: HandlerBuilder<Self>) -> HandlerBuilder<Self>
+  where
+    Self: Sized,
+  {
+    builder
+      .handle_write(|this, msg: MsgTick, _, access| {
+        let ctx = access.read_resource::<FabCtxHolder>().unwrap();
+        if let Some(sheet) = ctx.0.sprite_sheet(&this.sheet) {
+          this.advance(sheet);
+        }
+        msg
+      })
+      .handle_read(|this, msg: MsgDraw, me, access| {
+        let pos = access.query::<&Positioned>(me).unwrap();
+        let dims = access.query::<&HasDims>(me).unwrap();
+        let cam = access.read_resource::<Camera>().unwrap();
+
+        let assets = Resources::get();
+        let Some(sheet) = assets.get_sprite_sheet(&this.sheet) else {
+          return msg;
+        };
+        let Some(frame) = sheet.anim_frame(&this.anim, this.frame) else {
+          return msg;
+        };
+
+        let pos = pos.pos_at(msg.alpha());
+        let hitbox = Hitbox::new(pos.x, pos.y, dims.w, dims.h);
+        let bottom_center =
+          CoordVec::new(hitbox.x() + hitbox.w() / 2, hitbox.y() + hitbox.h())
+            - cam.center_at(msg.alpha());
+        let tex = assets.get_texture(sheet.texture());
+        this.draw_frame(tex, frame, bottom_center, mq::WHITE);
+
+        if let Some(blend) = &this.blend {
+          if let Some(frame) = sheet.anim_frame(&blend.anim, blend.frame) {
+            let fade = 1.0 - blend.timer as f32 / blend.ticks as f32;
+            let color = mq::Color::new(1.0, 1.0, 1.0, fade);
+            this.draw_frame(tex, frame, bottom_center, color);
+          }
+        }
+
+        msg
+      })
+  }
+}
+
+impl AnimatedSprite {
+  /// Draw a frame standing on the given point, in screen space.
+  fn draw_frame(
+    &self,
+    tex: mq::Texture2D,
+    frame: &Frame,
+    bottom_center: CoordVec,
+    color: mq::Color,
+  ) {
+    let corner = mq::vec2(
+      bottom_center.x as f32 - (frame.size.x / 2.0).floor(),
+      bottom_center.y as f32 - frame.size.y,
+    );
+    let offset_x = if self.flip_x {
+      frame.size.x - frame.offset.x - frame.source.w
+    } else {
+      frame.offset.x
+    };
+
+    mq::draw_texture_ex(
+      tex,
+      corner.x + offset_x,
+      corner.y + frame.offset.y,
+      color,
+      mq::DrawTextureParams {
+        source: Some(frame.source),
+        flip_x: self.flip_x,
+        ..Default::default()
+      },
+    );
+  }
+}
+
 /// Data component determining what gets rendered on top. Higher = more on top.
 /// `None` gets rendered under everything.
 #[derive(Debug, Serialize, Deserialize)]
@@ -108,3 +394,69 @@ impl Component for ZLevel {
     builder
   }
 }
+
+#[test]
+fn autotile_sprites_resolve_on_load() {
+  use crate::{ecm::setup_world, resources::Resources};
+
+  let resources = Resources::load_headless().unwrap();
+  let mut world = World::new();
+  setup_world(&mut world);
+  let ctx = FabCtx::new(resources.tilesets(), resources.sprite_sheets());
+
+  // A T shape, with the middle of the top bar at the origin
+  let mut spawn = |x, y| {
+    resources
+      .fabber()
+      .instantiate(
+        "wall",
+        world
+          .spawn()
+          .with(Positioned::new(CoordVec::new(x * 8, y * 8))),
+        &ctx,
+      )
+      .unwrap()
+  };
+  let middle = spawn(0, 0);
+  let left = spawn(-1, 0);
+  let stem = spawn(0, 1);
+  spawn(1, 0);
+
+  AutotileSprite::resolve_all(&world, &ctx);
+  let picked = |e| world.query::<&AutotileSprite>(e).unwrap().picked;
+  // The main tileset's wall tiles are in neighbor mask order
+  assert_eq!(picked(middle), Some(2 + 4 + 8));
+  assert_eq!(picked(left), Some(2));
+  assert_eq!(picked(stem), Some(1));
+}
+
+#[test]
+fn animated_sprite_stale_frame() {
+  use crate::resources::Resources;
+
+  let resources = Resources::load_headless().unwrap();
+  let sheet = &resources.sprite_sheets()["player-sheet"];
+
+  // Like a snapshot from when the animation had more frames
+  let mut sprite = AnimatedSprite {
+    sheet: "player-sheet".into(),
+    anim: "idle".into(),
+    flip_x: false,
+    frame: 999,
+    timer: 0,
+    plays: 0,
+    blend: None,
+  };
+  assert!(sheet.anim_frame(&sprite.anim, sprite.frame).is_none());
+  sprite.advance(sheet);
+  assert!(sheet.anim_frame(&sprite.anim, sprite.frame).is_some());
+
+  sprite.play_blended("run", 2);
+  assert!(sprite
+    .blend
+    .as_ref()
+    .is_some_and(|blend| blend.anim == "idle"));
+  sprite.advance(sheet);
+  sprite.advance(sheet);
+  assert!(sprite.blend.is_none());
+}
@@ -0,0 +1,132 @@
+use aglet::Direction8;
+use glam::{vec2, Vec2};
+use palkia::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ecm::{
+  component::Velocitized,
+  message::{MsgKill, MsgRecvHit, MsgTrigger, TriggerPhase},
+};
+
+/// Kills whatever bumps into it, if it's a [`Collider`], or overlaps it, if
+/// it's a [`Trigger`].
+///
+/// With `facing` set, like spikes pointing that way, it's only deadly from
+/// that side. Bumping into it has to be on that face, and overlapping it only
+/// kills things that aren't moving away in that direction, so you can jump up
+/// through floor spikes but not land on them.
+///
+/// [`Collider`]: crate::ecm::component::Collider
+/// [`Trigger`]: crate::ecm::component::Trigger
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Hazard {
+  #[serde(default)]
+  pub facing: Option<Direction8>,
+}
+
+impl Hazard {
+  /// How much the direction goes the way this is facing. Zero if it isn't
+  /// facing anywhere.
+  fn along_facing(&self, dir: Vec2) -> f32 {
+    match self.facing {
+      None => 0.0,
+      Some(facing) => {
+        let facing = facing.deltas();
+        dir.dot(vec2(facing.x as f32, facing.y as f32))
+      }
+    }
+  }
+}
+
+impl Component for Hazard {
+  fn register_handlers(builder: HandlerBuilder<Self>) -> HandlerBuilder<Self>
+  where
+    Self: Sized,
+  {
+    builder
+      .handle_read(|this, msg: MsgRecvHit, _, access| {
+        let normal = msg.normal().deltas();
+        let along = this.along_facing(vec2(normal.x as f32, normal.y as f32));
+        if this.facing.is_none() || along < 0.0 {
+          access.queue_dispatch(msg.bonker(), MsgKill);
+        }
+        msg
+      })
+      .handle_read(|this, msg: MsgTrigger, me, access| {
+        if msg.trigger() != me || msg.phase() == TriggerPhase::Exit {
+          return msg;
+        }
+        let vel = access
+          .query::<&Velocitized>(msg.visitor())
+          .map_or(Vec2::ZERO, |vel| vel.vel);
+        if this.along_facing(vel) <= 0.0 {
+          access.queue_dispatch(msg.visitor(), MsgKill);
+        }
+        msg
+      })
+  }
+}
+
+#[test]
+fn facing() {
+  let spikes = Hazard {
+    facing: Some(Direction8::North),
+  };
+  // Landing on them
+  assert!(spikes.along_facing(vec2(0.0, 100.0)) < 0.0);
+  // Jumping up through them
+  assert!(spikes.along_facing(vec2(10.0, -100.0)) > 0.0);
+  // Walking into the side
+  assert_eq!(spikes.along_facing(vec2(1.0, 0.0)), 0.0);
+}
+
+#[test]
+fn hazard_kills_then_respawns() {
+  use aglet::CoordVec;
+
+  use crate::{
+    ecm::component::{
+      HasDims, PlayerController, PlayerState, Positioned, Trigger,
+    },
+    states::StateGameplay,
+  };
+
+  let mut state = StateGameplay::sandbox(Vec::new());
+  let player = state.player();
+  let spawn = state.player_pos();
+
+  // Floor spikes right under where the player falls
+  state
+    .world_mut()
+    .spawn()
+    .with(Positioned::new(spawn + CoordVec::new(0, 16)))
+    .with(HasDims::new(16, 8))
+    .with(Trigger::default())
+    .with(Hazard {
+      facing: Some(Direction8::North),
+    })
+    .build();
+
+  let is_dead = |state: &StateGameplay| {
+    let controller = state.world().query::<&PlayerController>(player).unwrap();
+    matches!(controller.state(), PlayerState::Dead(_))
+  };
+
+  let mut ticks = 0;
+  while !is_dead(&state) {
+    state.run(1);
+    ticks += 1;
+    assert!(ticks < 60, "never landed on the spikes");
+  }
+  let died_at = state.player_pos();
+  state.run(1);
+  assert_eq!(state.player_pos(), died_at);
+
+  let mut ticks = 0;
+  while is_dead(&state) {
+    state.run(1);
+    ticks += 1;
+    assert!(ticks < 60, "never respawned");
+  }
+  assert_eq!(state.player_pos(), spawn);
+}
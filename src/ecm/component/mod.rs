@@ -1,12 +1,22 @@
+mod checkpoint;
 mod gfx;
+mod hazard;
+mod path;
 mod phys;
 mod player;
 mod swinging;
+mod tilemap;
+mod trigger;
 
+pub use checkpoint::*;
 pub use gfx::*;
+pub use hazard::*;
+pub use path::*;
 pub use phys::*;
 pub use player::*;
 pub use swinging::*;
+pub use tilemap::*;
+pub use trigger::*;
 
 use palkia::prelude::*;
 use serde::{Deserialize, Serialize};
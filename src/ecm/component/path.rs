@@ -0,0 +1,203 @@
+use aglet::CoordVec;
+use dialga::factory::ComponentFactory;
+use glam::{vec2, Vec2};
+use kdl::{KdlNode, KdlValue};
+use palkia::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{ecm::component::Mover, fabctx::FabCtx};
+
+/// Moves along the waypoints the level gave it in order, looping from the
+/// last one back to the first. With two waypoints, it goes back and forth.
+///
+/// If it's also a [`Collider`], it's a moving solid: it pushes movers out of
+/// its way, squishing them if they've got nowhere to go, and carries movers
+/// standing on top of it. If it isn't, it just goes through everything.
+///
+/// [`Collider`]: crate::ecm::component::Collider
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PathFollower {
+  waypoints: Vec<CoordVec>,
+  /// In pixels per second.
+  speed: f32,
+  /// Index of the waypoint it's heading towards.
+  #[serde(default)]
+  next: usize,
+  /// Sub-pixel movement left over from last tick.
+  #[serde(default)]
+  remainder: Vec2,
+}
+
+impl PathFollower {
+  pub fn new(waypoints: Vec<CoordVec>, speed: f32) -> Self {
+    Self {
+      waypoints,
+      speed,
+      next: 0,
+      remainder: Vec2::ZERO,
+    }
+  }
+
+  /// How far to move this tick, in whole pixels, from where it is now.
+  pub fn step(&mut self, pos: CoordVec, dt: f32) -> CoordVec {
+    let Some(target) = self.waypoints.get(self.next).copied() else {
+      return CoordVec::new(0, 0);
+    };
+    let to_target = target - pos;
+    let to_target = vec2(to_target.x as f32, to_target.y as f32);
+    let travel = self.speed * dt;
+
+    if to_target.length() <= travel {
+      self.next = (self.next + 1) % self.waypoints.len();
+      self.remainder = Vec2::ZERO;
+      return target - pos;
+    }
+
+    let movement = to_target.normalize() * travel + self.remainder;
+    let whole = movement.round();
+    self.remainder = movement - whole;
+    CoordVec::new(whole.x as i32, whole.y as i32)
+  }
+}
+
+impl Component for PathFollower {
+  fn register_handlers(builder: HandlerBuilder<Self>) -> HandlerBuilder<Self>
+  where
+    Self: Sized,
+  {
+    builder
+  }
+}
+
+/// Keeps this entity at a fixed offset from another one, moving along with
+/// it. Levels set this up for entities `attached` to others.
+///
+/// It moves after everything following a path has, so it's always right
+/// where it should be relative to a [`PathFollower`]. Like one, it pushes and
+/// carries things if it's a [`Collider`]. If the parent goes away, this
+/// stays where it was.
+///
+/// [`Collider`]: crate::ecm::component::Collider
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct AttachedTo {
+  pub parent: Entity,
+  pub offset: CoordVec,
+}
+
+impl Component for AttachedTo {
+  fn register_handlers(builder: HandlerBuilder<Self>) -> HandlerBuilder<Self>
+  where
+    Self: Sized,
+  {
+    builder
+  }
+}
+
+/// Factory for [`PathFollower`], with its waypoints from the level. A
+/// `speed` property on the level object overrides the blueprint's. Also adds
+/// a [`Mover`], so it gets tracked as something that moves.
+pub struct PathFollowerFactory;
+
+impl ComponentFactory<FabCtx> for PathFollowerFactory {
+  fn assemble<'a, 'w>(
+    &self,
+    mut builder: EntityBuilder<'a, 'w>,
+    node: &KdlNode,
+    ctx: &FabCtx,
+  ) -> eyre::Result<EntityBuilder<'a, 'w>> {
+    #[derive(Deserialize)]
+    struct Raw {
+      speed: f32,
+    }
+
+    let raw: Raw = knurdy::deserialize_node(node)?;
+    let speed = match ctx.prop("speed") {
+      None => raw.speed,
+      Some(KdlValue::Base10(it)) => *it as f32,
+      Some(KdlValue::Base10Float(it)) => *it as f32,
+      Some(other) => {
+        eyre::bail!("speed property should be a number, but was {}", other)
+      }
+    };
+
+    builder.insert(PathFollower::new(ctx.path.clone(), speed));
+    builder.insert(Mover::new());
+    Ok(builder)
+  }
+}
+
+#[test]
+fn speed_from_level_props() {
+  use crate::resources::Resources;
+
+  let resources = Resources::load_headless().unwrap();
+  let mut world = World::new();
+  crate::ecm::setup_world(&mut world);
+  let ctx = FabCtx::new(resources.tilesets(), resources.sprite_sheets());
+  // Its moving platform has a `speed` property
+  resources
+    .get_level("test/tiled-json")
+    .unwrap()
+    .instantiate(&mut world, &resources.fabber(), &ctx)
+    .unwrap();
+
+  let speeds = world
+    .entities()
+    .filter_map(|e| world.query::<&PathFollower>(e).map(|path| path.speed))
+    .collect::<Vec<_>>();
+  assert_eq!(speeds, vec![45.5]);
+}
+
+#[test]
+fn attached_moves_with_parent() {
+  use crate::{
+    ecm::component::Positioned, level::Level, resources::Resources,
+    states::StateGameplay,
+  };
+
+  let src = r##"
+    origin 0 0
+    tile-size 8
+    player-spawn 0 0
+    tileset "main"
+    map " "
+    entities {
+      - blueprint="moving-platform" x=-40 y=-40 {
+        path {
+          - 0 0
+          - 40 0
+        }
+        attached {
+          - blueprint="swingpoint" x=0 y=-24
+        }
+      }
+    }
+  "##;
+  let resources = Resources::load_headless().unwrap();
+  let level = Level::load_kdl(src, "test", &resources.tilesets()).unwrap();
+  let ctx = FabCtx::new(resources.tilesets(), resources.sprite_sheets());
+
+  let mut state = StateGameplay::sandbox(Vec::new());
+  let world = state.world_mut();
+  level.instantiate(world, &resources.fabber(), &ctx).unwrap();
+  let swingpoint = world
+    .entities()
+    .find(|e| world.query::<&AttachedTo>(*e).is_some())
+    .unwrap();
+  let attached = *world.query::<&AttachedTo>(swingpoint).unwrap();
+  assert!(world.query::<&PathFollower>(attached.parent).is_some());
+  assert_eq!(attached.offset, CoordVec::new(0, -24));
+
+  let pos_of = |state: &StateGameplay, e| {
+    state.world().query::<&Positioned>(e).unwrap().pos
+  };
+  let parent_start = pos_of(&state, attached.parent);
+  for _ in 0..20 {
+    state.run(3);
+    assert_eq!(
+      pos_of(&state, swingpoint),
+      pos_of(&state, attached.parent) + attached.offset
+    );
+  }
+  assert_ne!(pos_of(&state, attached.parent), parent_start);
+}
@@ -1,5 +1,8 @@
+use std::str::FromStr;
+
 use aglet::{CoordVec, Direction8};
 use ahash::AHashMap;
+use bitflags::bitflags;
 use dialga::factory::ComponentFactory;
 use kdl::KdlNode;
 use macroquad::prelude::Vec2;
@@ -8,11 +11,11 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
   ecm::{
-    message::{MsgDraw, MsgPhysicsTick, MsgSendHit},
+    message::{MsgPhysicsTick, MsgRespawn, MsgSendHit},
     resource::HitboxTracker,
   },
   fabctx::FabCtx,
-  geom::Hitbox,
+  geom::{lerp_coords, Hitbox},
 };
 
 /// Indicates this is placed in the world.
@@ -21,16 +24,35 @@ use crate::{
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Positioned {
   pub pos: CoordVec,
+  /// Where it was at the start of the last tick, to draw it between there
+  /// and here. `None` if it hasn't been around for a whole tick yet.
+  #[serde(skip)]
+  prev_pos: Option<CoordVec>,
 }
 
 impl Positioned {
   pub fn new(pos: CoordVec) -> Self {
-    Self { pos }
+    Self {
+      pos,
+      prev_pos: None,
+    }
   }
 
   pub fn from_vec(vec: Vec2) -> Self {
-    Self {
-      pos: CoordVec::new(vec.x.round() as _, vec.y.round() as _),
+    Self::new(CoordVec::new(vec.x.round() as _, vec.y.round() as _))
+  }
+
+  /// Remember the current position as where it was last tick.
+  pub fn remember_pos(&mut self) {
+    self.prev_pos = Some(self.pos);
+  }
+
+  /// Where to draw it, `alpha` of the way from last tick's position to this
+  /// one.
+  pub fn pos_at(&self, alpha: f32) -> CoordVec {
+    match self.prev_pos {
+      Some(prev) => lerp_coords(prev, self.pos, alpha),
+      None => self.pos,
     }
   }
 
@@ -46,12 +68,18 @@ impl Component for Positioned {
     Self: Sized,
   {
     builder
-      .register_create_callback(|_, me, access| {
-        if access.query::<&HasDims>(me).is_some() {
+      .register_create_callback(|this, me, access| {
+        if let Some(dims) = access.query::<&HasDims>(me) {
+          let is_mover = access.query::<&Mover>(me).is_some();
           let mut tracker = access.write_resource::<HitboxTracker>().unwrap();
-          tracker.on_create(me);
+          tracker.on_create(me, this.make_hitbox(*dims), is_mover);
         }
       })
+      .handle_write(|this, msg: MsgRespawn, _, _| {
+        // Forget the old position too, so it doesn't get drawn sliding over
+        *this = Positioned::new(msg.pos());
+        msg
+      })
       .register_remove_callback(|_, me, access| {
         // We don't query for HasDims because the entity will be dead right now
         // so we might try to remove some things that aren't in the tracker, oh well
@@ -89,12 +117,20 @@ impl Component for HasDims {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Mover {
   pub remainder: Vec2,
+  /// What kind of thing this is, as far as [`Collider`]s care.
+  #[serde(default)]
+  pub layers: CollisionLayers,
+  /// Which [`Collider`]s stop this.
+  #[serde(default = "CollisionLayers::all")]
+  pub mask: CollisionLayers,
 }
 
 impl Mover {
   pub fn new() -> Self {
     Self {
       remainder: Vec2::ZERO,
+      layers: CollisionLayers::default(),
+      mask: CollisionLayers::all(),
     }
   }
 
@@ -108,7 +144,10 @@ impl Component for Mover {
   where
     Self: Sized,
   {
-    builder
+    builder.handle_write(|this, msg: MsgRespawn, _, _| {
+      this.remainder = Vec2::ZERO;
+      msg
+    })
   }
 }
 
@@ -145,16 +184,21 @@ impl Component for Velocitized {
   where
     Self: Sized,
   {
-    builder.handle_write(|this, msg: MsgPhysicsTick, me, access| {
-      // if this.vel.length_squared() < 1.0 * msg.dt() {
-      //     this.vel = Vec2::ZERO;
-      // }
+    builder
+      .handle_write(|this, msg: MsgRespawn, _, _| {
+        this.vel = Vec2::ZERO;
+        msg
+      })
+      .handle_write(|this, msg: MsgPhysicsTick, me, access| {
+        // if this.vel.length_squared() < 1.0 * msg.dt() {
+        //     this.vel = Vec2::ZERO;
+        // }
 
-      let mut mover = access.query::<&mut Mover>(me).unwrap();
-      mover.move_by(this.vel * msg.dt());
+        let mut mover = access.query::<&mut Mover>(me).unwrap();
+        mover.move_by(this.vel * msg.dt());
 
-      msg
-    })
+        msg
+      })
   }
 }
 
@@ -198,6 +242,27 @@ impl Component for KinematicState {
         }
         msg
       })
+      .handle_write(|this, msg: MsgRespawn, _, _| {
+        this.touching.clear();
+        msg
+      })
+  }
+}
+
+/// Marker component for movers that move themselves, like the player.
+///
+/// Moving solids only push, carry and squish these. Other movers, like
+/// swingpoints, aren't meant to go anywhere, so solids go straight through
+/// them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Actor;
+
+impl Component for Actor {
+  fn register_handlers(builder: HandlerBuilder<Self>) -> HandlerBuilder<Self>
+  where
+    Self: Sized,
+  {
+    builder
   }
 }
 
@@ -272,9 +337,152 @@ impl Component for FrictionHaver {
   }
 }
 
-/// Marker component for things that should block movement.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Collider;
+bitflags! {
+  /// Kinds of things, so a [`Collider`] can only block some [`Mover`]s.
+  ///
+  /// Both of them get a set of `layers` they're on and a `mask` of layers
+  /// they bump into, and they only collide if each one's mask has the
+  /// other one in it. Everything's on `DEFAULT` and bumps into everything
+  /// unless it says otherwise.
+  ///
+  /// In KDL, these are written as names separated by spaces, like
+  /// `mask="player enemy"`. `all` and `none` work too.
+  #[derive(Serialize, Deserialize)]
+  #[serde(try_from = "String", into = "String")]
+  pub struct CollisionLayers: u32 {
+    const DEFAULT = 1 << 0;
+    const PLAYER = 1 << 1;
+    const ENEMY = 1 << 2;
+    /// The rods the player puts down to swing on.
+    const ROD = 1 << 3;
+  }
+}
+
+impl CollisionLayers {
+  const NAMES: [(&'static str, CollisionLayers); 4] = [
+    ("default", CollisionLayers::DEFAULT),
+    ("player", CollisionLayers::PLAYER),
+    ("enemy", CollisionLayers::ENEMY),
+    ("rod", CollisionLayers::ROD),
+  ];
+}
+
+impl Default for CollisionLayers {
+  fn default() -> Self {
+    CollisionLayers::DEFAULT
+  }
+}
+
+impl FromStr for CollisionLayers {
+  type Err = eyre::Report;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    s.split_whitespace().try_fold(Self::empty(), |acc, name| {
+      let layer = match name {
+        "all" => Self::all(),
+        "none" => Self::empty(),
+        _ => Self::NAMES
+          .iter()
+          .find(|(it, _)| *it == name)
+          .map(|(_, layer)| *layer)
+          .ok_or_else(|| eyre::eyre!("no collision layer named {:?}", name))?,
+      };
+      Ok(acc | layer)
+    })
+  }
+}
+
+impl TryFrom<String> for CollisionLayers {
+  type Error = eyre::Report;
+
+  fn try_from(value: String) -> Result<Self, Self::Error> {
+    value.parse()
+  }
+}
+
+impl From<CollisionLayers> for String {
+  fn from(layers: CollisionLayers) -> Self {
+    if layers.is_empty() {
+      return "none".to_owned();
+    }
+    CollisionLayers::NAMES
+      .iter()
+      .filter(|(_, layer)| layers.contains(*layer))
+      .map(|(name, _)| *name)
+      .collect::<Vec<_>>()
+      .join(" ")
+  }
+}
+
+/// Blocks the movement of [`Mover`]s, going by [`CollisionLayers`].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Collider {
+  /// What kind of thing this is.
+  #[serde(default)]
+  pub layers: CollisionLayers,
+  /// Which movers this stops.
+  #[serde(default = "CollisionLayers::all")]
+  pub mask: CollisionLayers,
+  /// If this is set, it's only solid from that side, like a ledge you can
+  /// jump up through from underneath and then stand on. If it's solid from
+  /// above, holding down drops through it.
+  #[serde(default, rename = "one-way")]
+  pub one_way: Option<Direction8>,
+}
+
+impl Collider {
+  /// Whether this and the mover agree that they bump into each other.
+  pub fn blocks(&self, mover: &Mover) -> bool {
+    self.mask.intersects(mover.layers) && mover.mask.intersects(self.layers)
+  }
+
+  /// Whether something taking a step of `motion` runs into this, when its
+  /// hitbox was `prev` before the step and this one's is `hb`.
+  ///
+  /// If it's one-way, it has to be heading into the solid side, and have
+  /// started out clear of it on that side. Otherwise it's passing through.
+  pub fn blocks_step(
+    &self,
+    motion: CoordVec,
+    prev: Hitbox,
+    hb: Hitbox,
+  ) -> bool {
+    let Some(from) = self.one_way else {
+      return true;
+    };
+    let from = from.deltas();
+    if motion.x * from.x + motion.y * from.y >= 0 {
+      return false;
+    }
+
+    [(from.x, prev.0.x, hb.0.x), (from.y, prev.0.y, hb.0.y)]
+      .into_iter()
+      .filter(|(side, _, _)| *side != 0)
+      .any(|(side, prev, hb)| {
+        if side < 0 {
+          prev.end <= hb.start
+        } else {
+          prev.start >= hb.end
+        }
+      })
+  }
+
+  /// Whether holding down lets you fall through it.
+  pub fn can_drop_through(&self) -> bool {
+    self.one_way.is_some_and(|from| from.deltas().y < 0)
+  }
+}
+
+impl Default for Collider {
+  fn default() -> Self {
+    Self {
+      layers: CollisionLayers::default(),
+      mask: CollisionLayers::all(),
+      one_way: None,
+    }
+  }
+}
+
 impl Component for Collider {
   fn register_handlers(builder: HandlerBuilder<Self>) -> HandlerBuilder<Self>
   where
@@ -284,9 +492,57 @@ impl Component for Collider {
   }
 }
 
+/// Makes a [`Collider`] a ramp instead of a box: it's only solid below a
+/// straight line from one side of its hitbox to the other.
+///
+/// Things walking along it step up and down it instead of running into it or
+/// falling off, as long as it isn't steeper than 45 degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Slope {
+  /// How high the floor is on the left edge, from 0 at the bottom of the
+  /// hitbox to 1 at the top.
+  pub left: f32,
+  /// How high the floor is on the right edge.
+  pub right: f32,
+}
+
+impl Slope {
+  /// Where the floor is at the given x, when this is at `hb`.
+  fn surface_at(&self, hb: Hitbox, x: i32) -> i32 {
+    let along = (x - hb.0.x.start) as f32 / hb.w() as f32;
+    let height = self.left + (self.right - self.left) * along;
+    hb.0.y.end - (height * hb.h() as f32).round() as i32
+  }
+
+  /// Whether `other` gets into the solid part, when this is at `hb`.
+  ///
+  /// It goes by the highest bit of floor under it, so it stands on the
+  /// corner of its hitbox, and meets the ground at the top of the slope flush.
+  pub fn overlaps(&self, hb: Hitbox, other: Hitbox) -> bool {
+    let start = other.0.x.start.max(hb.0.x.start);
+    let end = other.0.x.end.min(hb.0.x.end);
+    if start >= end || other.0.y.start >= hb.0.y.end {
+      return false;
+    }
+    let surface = self.surface_at(hb, start).min(self.surface_at(hb, end));
+    other.0.y.end > surface
+  }
+}
+
+impl Component for Slope {
+  fn register_handlers(builder: HandlerBuilder<Self>) -> HandlerBuilder<Self>
+  where
+    Self: Sized,
+  {
+    builder
+  }
+}
+
 // FACTORIES
 
 /// Factory for [`HasDims`], [`Mover`], [`Velocitized`], and [`KinematicState`].
+///
+/// The mover's [`CollisionLayers`] can go here too, as `layers` and `mask`.
 pub struct PhysicFactory;
 
 impl ComponentFactory<FabCtx> for PhysicFactory {
@@ -300,6 +556,10 @@ impl ComponentFactory<FabCtx> for PhysicFactory {
     struct Raw {
       width: i32,
       height: i32,
+      #[serde(default)]
+      layers: CollisionLayers,
+      #[serde(default = "CollisionLayers::all")]
+      mask: CollisionLayers,
     }
 
     let raw: Raw = knurdy::deserialize_node(node)?;
@@ -308,7 +568,11 @@ impl ComponentFactory<FabCtx> for PhysicFactory {
       w: raw.width,
       h: raw.height,
     });
-    builder.insert(Mover::new());
+    builder.insert(Mover {
+      layers: raw.layers,
+      mask: raw.mask,
+      ..Mover::new()
+    });
     builder.insert(Velocitized::still());
     builder.insert(KinematicState::default());
     Ok(builder)
@@ -331,3 +595,128 @@ impl ComponentFactory<FabCtx> for FrictionFactory {
     Ok(builder)
   }
 }
+
+#[test]
+fn one_way_platform() {
+  let ledge = Collider {
+    one_way: Some(Direction8::North),
+    ..Collider::default()
+  };
+  let hb = Hitbox::new(0, 0, 8, 8);
+  let above = Hitbox::new(0, -10, 8, 12);
+  let inside = Hitbox::new(0, -6, 8, 12);
+
+  // Landing on it
+  assert!(ledge.blocks_step(CoordVec::new(0, 1), above, hb));
+  // Jumping up through it
+  assert!(!ledge.blocks_step(CoordVec::new(0, -1), inside, hb));
+  // Falling back down while still partway through
+  assert!(!ledge.blocks_step(CoordVec::new(0, 1), inside, hb));
+  // Walking into the side
+  assert!(!ledge.blocks_step(
+    CoordVec::new(1, 0),
+    Hitbox::new(-8, 0, 8, 12),
+    hb
+  ));
+}
+
+#[test]
+fn one_way_platforms() {
+  use crate::{controls::ControlState, states::StateGameplay};
+
+  let down = ControlState {
+    movement: Vec2::Y,
+    ..Default::default()
+  };
+  let mut ticks = vec![ControlState::default(); 30];
+  ticks.extend(vec![down; 10]);
+  let mut state = StateGameplay::sandbox(ticks);
+  let spawn = state.player_pos();
+  let ledge = |state: &mut StateGameplay, center, mask| {
+    state
+      .world_mut()
+      .spawn()
+      .with(Positioned::new(center))
+      .with(HasDims::new(16, 8))
+      .with(Collider {
+        mask,
+        one_way: Some(Direction8::North),
+        ..Collider::default()
+      })
+      .build();
+  };
+  // The player goes right through one that only stops enemies
+  ledge(
+    &mut state,
+    spawn + CoordVec::new(0, 12),
+    CollisionLayers::ENEMY,
+  );
+  ledge(
+    &mut state,
+    spawn + CoordVec::new(0, 24),
+    CollisionLayers::all(),
+  );
+
+  // Land on it
+  state.run(30);
+  assert_eq!(state.player_pos(), spawn + CoordVec::new(0, 14));
+
+  // Hold down to drop through
+  state.run(10);
+  assert!(state.player_pos().y > spawn.y + 14);
+}
+
+#[test]
+fn collision_layers() {
+  let layers: CollisionLayers = "player  enemy".parse().unwrap();
+  assert_eq!(layers, CollisionLayers::PLAYER | CollisionLayers::ENEMY);
+  assert_eq!(String::from(layers), "player enemy");
+  assert!("player goblin".parse::<CollisionLayers>().is_err());
+
+  let player = Mover {
+    layers: CollisionLayers::PLAYER,
+    ..Mover::new()
+  };
+  let enemy = Mover {
+    layers: CollisionLayers::ENEMY,
+    ..Mover::new()
+  };
+  let rod = Collider {
+    layers: CollisionLayers::ROD,
+    mask: CollisionLayers::ENEMY,
+    ..Collider::default()
+  };
+  assert!(Collider::default().blocks(&player));
+  assert!(!rod.blocks(&player));
+  assert!(rod.blocks(&enemy));
+
+  let ghost = Mover {
+    mask: CollisionLayers::empty(),
+    ..Mover::new()
+  };
+  assert!(!Collider::default().blocks(&ghost));
+}
+
+#[test]
+fn slope() {
+  // Going up to the right
+  let slope = Slope {
+    left: 0.0,
+    right: 1.0,
+  };
+  let hb = Hitbox::new(4, 4, 8, 8);
+  let standing_at = |right_edge: i32, bottom: i32| {
+    Hitbox::new(right_edge - 4, bottom - 6, 8, 12)
+  };
+
+  assert!(!slope.overlaps(hb, standing_at(3, 5)));
+  assert!(slope.overlaps(hb, standing_at(3, 6)));
+  // Flush with the top, where the next tile over would be
+  assert!(!slope.overlaps(hb, standing_at(8, 0)));
+  assert!(slope.overlaps(hb, standing_at(8, 1)));
+  // Walking on from the bottom
+  assert!(!slope.overlaps(hb, standing_at(1, 7)));
+  assert!(slope.overlaps(hb, standing_at(1, 8)));
+  // Underneath
+  assert!(!slope.overlaps(hb, Hitbox::new(4, 14, 8, 12)));
+}
@@ -1,13 +1,17 @@
 //! https://gmtk.itch.io/platformer-toolkit/devlog/395523/behind-the-code
 
+mod anim;
 mod stats;
 mod swinging;
 
+pub use anim::{PlayerAnimFactory, PlayerAnimator};
+pub use stats::{PlayerStats, PlayerStatsOverrides};
+
 use aglet::{CoordVec, Direction8};
 use dialga::factory::ComponentFactory;
 use glam::{vec2, Vec2};
 use kdl::KdlNode;
-use macroquad::prelude::{self as mq, Color};
+use macroquad::prelude as mq;
 use palkia::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -15,18 +19,13 @@ use crate::{
   controls::ControlState,
   ecm::{
     component::{KinematicState, Positioned, Velocitized},
-    message::{MsgDraw, MsgPhysicsTick},
-    resource::Camera,
+    message::{MsgDraw, MsgKill, MsgPhysicsTick, MsgRespawn},
+    resource::{Camera, RespawnPoint, TheControls},
   },
   fabctx::FabCtx,
   geom::{signum0, Hitbox},
-  gfx::{de_hexcol, hexcol, ser_hexcol},
 };
 
-use self::stats::PlayerStats;
-
-use super::HasDims;
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PlayerController {
   was_pressing_jump: bool,
@@ -40,11 +39,11 @@ pub struct PlayerController {
   deployed_rod_entity: Option<Entity>,
 
   state: PlayerState,
-  #[serde(serialize_with = "ser_hexcol")]
-  #[serde(deserialize_with = "de_hexcol")]
-  color: Color,
 
   stats: PlayerStats,
+  /// What the stats were calculated from, so they can be changed and
+  /// calculated again.
+  stat_overrides: PlayerStatsOverrides,
 
   /// For the benefit of drawing.
   /// The skip attr will "deserialize" it as default
@@ -59,16 +58,24 @@ impl Component for PlayerController {
   {
     builder
       .handle_write(|this, msg: MsgPhysicsTick, me, access| {
-        let controls = ControlState::calculate();
+        let controls = access.read_resource::<TheControls>().unwrap().0;
         this.update_from_controls(me, msg.dt(), controls, access);
         msg
       })
+      .handle_write(|this, msg: MsgKill, me, access| {
+        this.die(me, access);
+        msg
+      })
+      .handle_write(|this, msg: MsgRespawn, _, access| {
+        this.on_respawn(access);
+        msg
+      })
       .handle_read(Self::on_draw)
   }
 }
 
 impl PlayerController {
-  pub fn new(color: Color) -> Self {
+  pub fn new(stat_overrides: PlayerStatsOverrides) -> Self {
     Self {
       was_pressing_jump: false,
       jump_buffer_countdown: 0.0,
@@ -76,16 +83,33 @@ impl PlayerController {
       deployed_rod_in_air: false,
       deployed_rod_entity: None,
 
-      color,
-
       state: PlayerState::default(),
 
-      stats: PlayerStats::default(),
+      stats: PlayerStats::new(&stat_overrides),
+      stat_overrides,
 
       cached_controls: None,
     }
   }
 
+  pub fn state(&self) -> &PlayerState {
+    &self.state
+  }
+
+  pub fn stats(&self) -> &PlayerStats {
+    &self.stats
+  }
+
+  pub fn stat_overrides(&self) -> &PlayerStatsOverrides {
+    &self.stat_overrides
+  }
+
+  /// Change what the stats are overridden with, and recalculate them.
+  pub fn set_stat_overrides(&mut self, overrides: PlayerStatsOverrides) {
+    self.stats = PlayerStats::new(&overrides);
+    self.stat_overrides = overrides;
+  }
+
   pub fn update_from_controls(
     &mut self,
     me: Entity,
@@ -102,6 +126,7 @@ impl PlayerController {
       PlayerState::Swinging(..) => {
         self.swinging_movement(access, me, controls, dt)
       }
+      PlayerState::Dead(..) => self.dead_movement(me, dt, access),
     }
 
     let jump_rising_edge = controls.jump && !self.was_pressing_jump;
@@ -113,13 +138,56 @@ impl PlayerController {
     self.was_pressing_jump = controls.jump;
 
     if controls.reset {
-      let mut pos = access.query::<&mut Positioned>(me).unwrap();
-      pos.pos = CoordVec::new(0, 0);
+      self.respawn(me, access);
     }
 
     self.cached_controls = Some(controls);
   }
 
+  /// Stop everything and lie there for a moment, then respawn.
+  pub fn die(&mut self, me: Entity, access: &ListenerWorldAccess) {
+    if matches!(self.state, PlayerState::Dead(..)) {
+      return;
+    }
+    access.query::<&mut Velocitized>(me).unwrap().vel = Vec2::ZERO;
+    self.state = PlayerState::Dead(Dead {
+      respawn_countdown: self.stats.respawn_delay,
+    });
+  }
+
+  /// Send the player back to the last checkpoint.
+  pub fn respawn(&mut self, me: Entity, access: &ListenerWorldAccess) {
+    let pos = access.read_resource::<RespawnPoint>().unwrap().0;
+    // Everything on the player needs to hear about this, including us, so
+    // wait until we're unlocked
+    access.queue_dispatch(me, MsgRespawn::new(pos));
+  }
+
+  /// Start over from scratch, except for the stats.
+  fn on_respawn(&mut self, access: &ListenerWorldAccess) {
+    if let Some(rod) = self.deployed_rod_entity {
+      access.lazy_despawn(rod);
+    }
+    let stat_overrides = std::mem::take(&mut self.stat_overrides);
+    *self = PlayerController::new(stat_overrides);
+  }
+
+  fn dead_movement(
+    &mut self,
+    me: Entity,
+    dt: f32,
+    access: &ListenerWorldAccess,
+  ) {
+    let PlayerState::Dead(ref mut dead) = self.state else {
+      unreachable!()
+    };
+    access.query::<&mut Velocitized>(me).unwrap().vel = Vec2::ZERO;
+    dead.respawn_countdown -= dt;
+    if dead.respawn_countdown <= 0.0 {
+      self.respawn(me, access);
+    }
+  }
+
   fn normal_movement(
     &mut self,
     entity: Entity,
@@ -262,17 +330,10 @@ impl PlayerController {
     me: Entity,
     access: &ListenerWorldAccess,
   ) -> MsgDraw {
-    let pos = access.query::<&Positioned>(me).unwrap();
-    let dims = access.query::<&HasDims>(me).unwrap();
-    let cam = access.read_resource::<Camera>().unwrap();
-    let corner = pos.pos - CoordVec::new(dims.w / 2, dims.h / 2) - cam.center();
-    mq::draw_rectangle(
-      corner.x as f32,
-      corner.y as f32,
-      dims.w as f32,
-      dims.h as f32,
-      self.color,
-    );
+    let cam_center = access
+      .read_resource::<Camera>()
+      .unwrap()
+      .center_at(msg.alpha());
     if self.stats.debugdraw_grab_hbs {
       if let Some(ref controls) = self.cached_controls {
         let anchor_delta = if controls.movement.length_squared() < 0.0001 {
@@ -281,11 +342,11 @@ impl PlayerController {
           controls.movement.normalize()
         };
         let player_pos = access.query::<&Positioned>(me).unwrap();
-        for hb in grab_extant_rod_hbs(player_pos.pos, anchor_delta, &self.stats)
-        {
+        let player_pos = player_pos.pos_at(msg.alpha());
+        for hb in grab_extant_rod_hbs(player_pos, anchor_delta, &self.stats) {
           mq::draw_rectangle(
-            (hb.x() - cam.center().x) as f32,
-            (hb.y() - cam.center().y) as f32,
+            (hb.x() - cam_center.x) as f32,
+            (hb.y() - cam_center.y) as f32,
             hb.w() as _,
             hb.h() as _,
             mq::Color::from_rgba(255, 120, 0, 100),
@@ -326,9 +387,10 @@ fn acc() {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-enum PlayerState {
+pub enum PlayerState {
   Normal(Normal),
   Swinging(Swinging),
+  Dead(Dead),
 }
 
 impl Default for PlayerState {
@@ -338,14 +400,14 @@ impl Default for PlayerState {
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
-struct Normal {
-  state: NormalState,
+pub struct Normal {
+  pub state: NormalState,
   /// prevent swinging until swing is released and pressed again
-  was_swinging: bool,
+  pub was_swinging: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
-enum NormalState {
+pub enum NormalState {
   #[default]
   OnGround,
   FallingFromLedge {
@@ -356,12 +418,20 @@ enum NormalState {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct Swinging {
+pub struct Swinging {
   /// 0 = straight down; tau/4 = left
-  angle: f32,
-  vel: f32,
-  anchor_pos: Vec2,
-  swingee: Entity,
+  pub angle: f32,
+  pub vel: f32,
+  pub anchor_pos: Vec2,
+  pub swingee: Entity,
+  /// Where the swingee was last tick, so the anchor can move along with it.
+  pub swingee_pos: CoordVec,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Dead {
+  /// Seconds until coming back at the last checkpoint.
+  pub respawn_countdown: f32,
 }
 
 // ===
@@ -375,14 +445,8 @@ impl ComponentFactory<FabCtx> for PlayerFactory {
     node: &KdlNode,
     _ctx: &FabCtx,
   ) -> eyre::Result<EntityBuilder<'a, 'w>> {
-    #[derive(Deserialize)]
-    struct Raw {
-      color: u32,
-    }
-
-    let raw: Raw = knurdy::deserialize_node(node)?;
-
-    builder.insert(PlayerController::new(hexcol(raw.color)));
+    let overrides: PlayerStatsOverrides = knurdy::deserialize_node(node)?;
+    builder.insert(PlayerController::new(overrides));
     Ok(builder)
   }
 }
@@ -416,3 +480,54 @@ fn grab_extant_rod_hbs(
     },
   ))
 }
+
+#[test]
+fn reset_respawns_cleanly() {
+  use crate::{
+    ecm::component::{Mover, SwingableOn},
+    states::StateGameplay,
+  };
+
+  let mut ticks = StateGameplay::swing_script();
+  let swinging = ticks.len();
+  // Still holding on
+  let reset = ControlState {
+    reset: true,
+    ..*ticks.last().unwrap()
+  };
+  ticks.push(reset);
+  let mut state = StateGameplay::sandbox(ticks);
+  let player = state.player();
+  let swingables = |state: &StateGameplay| {
+    let world = state.world();
+    world
+      .entities()
+      .filter(|e| world.query::<&SwingableOn>(*e).is_some())
+      .count()
+  };
+  let is_swinging = |state: &StateGameplay| {
+    let controller = state.world().query::<&PlayerController>(player).unwrap();
+    matches!(controller.state(), PlayerState::Swinging(_))
+  };
+  let swingables_before = swingables(&state);
+
+  let checkpoint = CoordVec::new(20, -30);
+  state
+    .world_mut()
+    .write_resource::<RespawnPoint>()
+    .unwrap()
+    .0 = checkpoint;
+  state.run(swinging);
+  assert!(is_swinging(&state));
+  // On our own rod
+  assert_eq!(swingables(&state), swingables_before + 1);
+
+  state.run(1);
+  assert_eq!(state.player_pos(), checkpoint);
+  let world = state.world();
+  assert_eq!(world.query::<&Velocitized>(player).unwrap().vel, Vec2::ZERO);
+  assert_eq!(world.query::<&Mover>(player).unwrap().remainder, Vec2::ZERO);
+  let controller = world.query::<&PlayerController>(player).unwrap();
+  assert!(matches!(controller.state(), PlayerState::Normal(_)));
+  assert_eq!(swingables(&state), swingables_before);
+}
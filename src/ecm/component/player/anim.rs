@@ -0,0 +1,302 @@
+use super::{NormalState, PlayerController, PlayerState};
+
+use dialga::factory::ComponentFactory;
+use glam::Vec2;
+use kdl::KdlNode;
+use palkia::prelude::*;
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+
+use crate::{
+  ecm::{
+    component::{AnimatedSprite, Velocitized},
+    message::{MsgRespawn, MsgTick},
+  },
+  fabctx::FabCtx,
+};
+
+/// Picks the player's animation by reading the [`PlayerController`] and
+/// [`Velocitized`], according to rules declared in the blueprint.
+///
+/// Every tick, the first rule whose conditions all hold picks the animation,
+/// unless the current one is still being held.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlayerAnimator {
+  rules: Vec<AnimRule>,
+
+  /// Index of the rule that picked the current animation.
+  #[serde(default)]
+  current: Option<usize>,
+  /// Ticks since the current rule got picked.
+  #[serde(default)]
+  ticks: u32,
+  /// What the player was doing last tick.
+  #[serde(default)]
+  prev_state: Option<StateKind>,
+}
+
+/// Leaving a condition out means it doesn't matter.
+#[derive(Debug, Serialize, Deserialize)]
+struct AnimRule {
+  anim: SmolStr,
+
+  state: Option<StateKind>,
+  /// Only switch to this from the given animation.
+  from: Option<SmolStr>,
+  /// Only matches on the tick the player stops being in this state.
+  from_state: Option<StateKind>,
+
+  /// Whether the player is moving horizontally.
+  moving: Option<bool>,
+  /// Whether the player is holding the opposite way they're moving.
+  turning: Option<bool>,
+  /// Whether the player is holding down.
+  plummeting: Option<bool>,
+  vel_y_min: Option<f32>,
+  vel_y_max: Option<f32>,
+  /// In degrees away from hanging straight down, on either side. These only
+  /// match while swinging.
+  angle_min: Option<f32>,
+  angle_max: Option<f32>,
+
+  /// Once picked, keep this for at least this many ticks.
+  #[serde(default)]
+  hold: u32,
+  /// When switching to this, fade the old animation out over this many ticks.
+  #[serde(default)]
+  blend: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum StateKind {
+  OnGround,
+  FallingFromLedge,
+  JumpingUp,
+  Falling,
+  Swinging,
+  Dead,
+}
+
+/// Everything the rules can look at.
+struct AnimInputs {
+  state: StateKind,
+  vel: Vec2,
+  movement: Vec2,
+  /// Degrees, if swinging.
+  swing_angle: Option<f32>,
+}
+
+impl PlayerAnimator {
+  fn new(rules: Vec<AnimRule>) -> Self {
+    Self {
+      rules,
+      current: None,
+      ticks: 0,
+      prev_state: None,
+    }
+  }
+
+  /// Returns the animation to play, and how many ticks to blend into it over.
+  fn update(&mut self, inputs: &AnimInputs) -> Option<(&str, u32)> {
+    self.ticks = self.ticks.saturating_add(1);
+    let held = self
+      .current
+      .is_some_and(|idx| self.ticks < self.rules[idx].hold);
+
+    if !held {
+      let current_anim = self.current.map(|idx| self.rules[idx].anim.as_str());
+      let picked = self
+        .rules
+        .iter()
+        .position(|rule| rule.matches(inputs, current_anim, self.prev_state));
+      if picked.is_some() && picked != self.current {
+        self.current = picked;
+        self.ticks = 0;
+      }
+    }
+    self.prev_state = Some(inputs.state);
+
+    self.current.map(|idx| {
+      let rule = &self.rules[idx];
+      (rule.anim.as_str(), rule.blend)
+    })
+  }
+}
+
+impl Component for PlayerAnimator {
+  fn register_handlers(builder: HandlerBuilder<Self>) -> HandlerBuilder<Self>
+  where
+    Self: Sized,
+  {
+    builder
+      .handle_write(|this, msg: MsgRespawn, _, _| {
+        this.current = None;
+        this.ticks = 0;
+        this.prev_state = None;
+        msg
+      })
+      .handle_write(|this, msg: MsgTick, me, access| {
+        let Some(player) = access.query::<&PlayerController>(me) else {
+          return msg;
+        };
+        let vel = access.query::<&Velocitized>(me).unwrap();
+        let inputs = AnimInputs::new(&player, &vel);
+
+        if let Some(mut sprite) = access.query::<&mut AnimatedSprite>(me) {
+          if let Some((anim, blend)) = this.update(&inputs) {
+            sprite.play_blended(anim, blend);
+          }
+          if inputs.movement.x != 0.0 {
+            sprite.set_flip_x(inputs.movement.x < 0.0);
+          }
+        }
+
+        msg
+      })
+  }
+}
+
+impl AnimRule {
+  fn matches(
+    &self,
+    inputs: &AnimInputs,
+    current_anim: Option<&str>,
+    prev_state: Option<StateKind>,
+  ) -> bool {
+    let moving = inputs.vel.x != 0.0;
+    let turning = inputs.movement.x != 0.0
+      && moving
+      && inputs.movement.x.signum() != inputs.vel.x.signum();
+    let plummeting = inputs.movement.y > 0.0;
+
+    let is = |want: Option<bool>, have: bool| want.is_none_or(|w| w == have);
+    let above = |min: Option<f32>, have: f32| min.is_none_or(|m| have >= m);
+    let below = |max: Option<f32>, have: f32| max.is_none_or(|m| have <= m);
+
+    self.state.is_none_or(|state| state == inputs.state)
+      && self
+        .from
+        .as_ref()
+        .is_none_or(|from| current_anim == Some(from.as_str()))
+      && self
+        .from_state
+        .is_none_or(|from| prev_state == Some(from) && inputs.state != from)
+      && is(self.moving, moving)
+      && is(self.turning, turning)
+      && is(self.plummeting, plummeting)
+      && above(self.vel_y_min, inputs.vel.y)
+      && below(self.vel_y_max, inputs.vel.y)
+      && match inputs.swing_angle {
+        Some(angle) => {
+          above(self.angle_min, angle) && below(self.angle_max, angle)
+        }
+        None => self.angle_min.is_none() && self.angle_max.is_none(),
+      }
+  }
+}
+
+impl AnimInputs {
+  fn new(player: &PlayerController, vel: &Velocitized) -> Self {
+    let (state, swing_angle) = match &player.state {
+      PlayerState::Normal(normal) => {
+        let state = match normal.state {
+          NormalState::OnGround => StateKind::OnGround,
+          NormalState::FallingFromLedge { .. } => StateKind::FallingFromLedge,
+          NormalState::JumpingUp => StateKind::JumpingUp,
+          NormalState::Falling => StateKind::Falling,
+        };
+        (state, None)
+      }
+      PlayerState::Swinging(swinging) => {
+        // Wrap it into -180..180 first
+        let angle =
+          (swinging.angle.to_degrees() + 180.0).rem_euclid(360.0) - 180.0;
+        (StateKind::Swinging, Some(angle.abs()))
+      }
+      PlayerState::Dead(..) => (StateKind::Dead, None),
+    };
+    Self {
+      state,
+      vel: vel.vel,
+      movement: player
+        .cached_controls
+        .as_ref()
+        .map_or(Vec2::ZERO, |controls| controls.movement),
+      swing_angle,
+    }
+  }
+}
+
+pub struct PlayerAnimFactory;
+
+impl ComponentFactory<FabCtx> for PlayerAnimFactory {
+  fn assemble<'a, 'w>(
+    &self,
+    mut builder: EntityBuilder<'a, 'w>,
+    node: &KdlNode,
+    _ctx: &FabCtx,
+  ) -> eyre::Result<EntityBuilder<'a, 'w>> {
+    let rules: Vec<AnimRule> = knurdy::deserialize_node(node)?;
+    builder.insert(PlayerAnimator::new(rules));
+    Ok(builder)
+  }
+}
+
+#[cfg(test)]
+fn test_animator(rules: &str) -> PlayerAnimator {
+  let doc: kdl::KdlDocument =
+    format!("player-anims {{ {} }}", rules).parse().unwrap();
+  PlayerAnimator::new(knurdy::deserialize_node(&doc.nodes()[0]).unwrap())
+}
+
+#[cfg(test)]
+fn on_ground(vel_x: f32, movement_x: f32) -> AnimInputs {
+  AnimInputs {
+    state: StateKind::OnGround,
+    vel: Vec2::new(vel_x, 0.0),
+    movement: Vec2::new(movement_x, 0.0),
+    swing_angle: None,
+  }
+}
+
+#[test]
+fn anim_rule_priority() {
+  let mut animator = test_animator(
+    r#"
+    - anim="run" state="on-ground" moving=true
+    - anim="idle" state="on-ground"
+    - anim="fall"
+    "#,
+  );
+
+  assert_eq!(animator.update(&on_ground(0.0, 0.0)), Some(("idle", 0)));
+  // Both on-ground rules match, and the first one wins
+  assert_eq!(animator.update(&on_ground(10.0, 1.0)), Some(("run", 0)));
+
+  let falling = AnimInputs {
+    state: StateKind::Falling,
+    vel: Vec2::new(0.0, 50.0),
+    movement: Vec2::ZERO,
+    swing_angle: None,
+  };
+  assert_eq!(animator.update(&falling), Some(("fall", 0)));
+}
+
+#[test]
+fn anim_hold() {
+  let mut animator = test_animator(
+    r#"
+    - anim="turn" state="on-ground" turning=true hold=3 blend=2
+    - anim="run" state="on-ground" moving=true
+    - anim="idle" state="on-ground"
+    "#,
+  );
+
+  assert_eq!(animator.update(&on_ground(10.0, -1.0)), Some(("turn", 2)));
+  // Stopped turning, but it's held for 2 more ticks
+  for _ in 0..2 {
+    assert_eq!(animator.update(&on_ground(0.0, 0.0)), Some(("turn", 2)));
+  }
+  assert_eq!(animator.update(&on_ground(0.0, 0.0)), Some(("idle", 0)));
+}
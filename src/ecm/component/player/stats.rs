@@ -72,65 +72,166 @@ pub struct PlayerStats {
   pub grab_extant_swingable_radius_increment: i32,
   pub grab_extant_start_size: i32,
 
+  /// How long to lie there after dying before respawning, in seconds.
+  pub respawn_delay: f32,
+
+  /// How many pixels sideways the player can get nudged to slip past a
+  /// ceiling corner they bonked their head on.
+  pub jump_corner_correction: i32,
+  /// How many pixels up the player can get nudged, when moving sideways in
+  /// the air, to get onto a ledge they only just didn't clear.
+  pub ledge_corner_correction: i32,
+
   pub debugdraw_grab_hbs: bool,
 }
 
+/// [`PlayerStats`] as written in the `player` blueprint node. Anything left
+/// out falls back to the default, and derived values like
+/// `jump_impulse_vel` get worked out from the ones that are there unless
+/// they're given too.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PlayerStatsOverrides {
+  pub walk_terminal_vel: Option<f32>,
+  pub walk_accel: Option<f32>,
+  pub walk_friction: Option<f32>,
+  pub walk_turn_accel: Option<f32>,
+  pub walk_overfast_friction: Option<f32>,
+  pub air_terminal_vel: Option<f32>,
+  pub air_accel: Option<f32>,
+  pub air_friction: Option<f32>,
+  pub air_turn_accel: Option<f32>,
+  pub air_overfast_friction: Option<f32>,
+  pub jump_height: Option<f32>,
+  pub time_to_jump_apex: Option<f32>,
+  pub jump_impulse_vel: Option<f32>,
+  pub jump_gravity: Option<f32>,
+  pub jump_release_gravity: Option<f32>,
+  pub falling_gravity: Option<f32>,
+  pub coyote_gravity: Option<f32>,
+  pub fall_terminal_vel: Option<f32>,
+  pub plummet_terminal_vel: Option<f32>,
+  pub fall_friction_y: Option<f32>,
+  pub plummet_friction_y: Option<f32>,
+  pub coyote_time: Option<f32>,
+  pub jump_buffer_len: Option<f32>,
+  pub rod_anchor_dist: Option<f32>,
+  pub vel_to_swing_vel_rate: Option<f32>,
+  pub swing_gravity: Option<f32>,
+  pub swing_friction: Option<f32>,
+  pub swing_too_far_angle: Option<f32>,
+  pub swing_too_far_gravity: Option<f32>,
+  pub player_swing_acc: Option<f32>,
+  pub swing_terminal_vel: Option<f32>,
+  pub swing_vel_to_vel_rate_x: Option<f32>,
+  pub swing_vel_to_vel_rate_y: Option<f32>,
+  pub start_grab_speed_cheat_min: Option<f32>,
+  pub start_grab_speed_cheat_max: Option<f32>,
+  pub angle_to_cheat_launch_vel_at: Option<f32>,
+  pub angle_launch_vel_cheat_factor: Option<f32>,
+  pub grab_extant_step_size: Option<f32>,
+  pub grab_extant_step_count: Option<usize>,
+  pub grab_extant_swingable_radius: Option<i32>,
+  pub grab_extant_swingable_radius_increment: Option<i32>,
+  pub grab_extant_start_size: Option<i32>,
+  pub respawn_delay: Option<f32>,
+  pub jump_corner_correction: Option<i32>,
+  pub ledge_corner_correction: Option<i32>,
+  pub debugdraw_grab_hbs: Option<bool>,
+}
+
 impl Default for PlayerStats {
   fn default() -> Self {
-    let walk_terminal_vel = 14.0 * 8.0;
+    Self::new(&PlayerStatsOverrides::default())
+  }
+}
+
+impl PlayerStats {
+  /// Fill in everything the overrides leave out with the defaults, working
+  /// out the derived values from whatever got overridden.
+  pub fn new(o: &PlayerStatsOverrides) -> Self {
+    let walk_terminal_vel = o.walk_terminal_vel.unwrap_or(14.0 * 8.0);
     // get up to speed in 0.3 seconds
-    let walk_accel = walk_terminal_vel / 0.3;
-    let walk_friction = walk_terminal_vel * 60.0 / 2.0;
-    let walk_turn_accel = walk_terminal_vel * 60.0 / 1.0;
-    let walk_overfast_friction = walk_terminal_vel * 60.0 / 10.0;
-
-    let air_terminal_vel = walk_terminal_vel;
-    let air_accel = air_terminal_vel * 60.0 / 16.0;
-    let air_friction = air_terminal_vel * 60.0 / 16.0;
-    let air_turn_accel = air_terminal_vel * 60.0 / 8.0;
-    let air_overfast_friction = air_terminal_vel * 60.0 / 30.0;
-
-    let jump_height = 40.0;
-    let time_to_jump_apex = 0.45;
-    let jump_impulse_vel = 2.0 * jump_height / time_to_jump_apex;
-    let jump_gravity = jump_impulse_vel / time_to_jump_apex;
-    let jump_release_gravity = jump_gravity * 3.0;
-    let falling_gravity = jump_gravity * 2.5;
-    let coyote_gravity = falling_gravity * 0.5;
-
-    let fall_terminal_vel = 270.0;
-    let fall_friction_y = fall_terminal_vel * 60.0 / 15.0;
-    let plummet_terminal_vel = 400.0;
-    let plummet_friction_y = plummet_terminal_vel * 60.0 / 30.0;
-
-    let coyote_time = 0.05;
-    let jump_buffer_len = 0.1;
-
-    let rod_anchor_dist = 12.0;
-    let vel_to_swing_vel_rate = 0.05;
-    let swing_gravity = 5.0;
-    let swing_friction = 0.05;
-    let swing_too_far_angle = TAU / 4.0;
-    let swing_too_far_gravity = 10.0;
-
-    let player_swing_acc = 4.0;
-
-    let swing_terminal_vel = 13.0;
-    let swing_vel_to_vel_rate_y = 2.5;
-    let swing_vel_to_vel_rate_x = swing_vel_to_vel_rate_y * 0.9;
-    let start_grab_speed_cheat_min = 1.5;
-    let start_grab_speed_cheat_max = 9.0;
-
-    let angle_to_cheat_launch_vel_at = TAU * 0.225;
-    let angle_launch_vel_cheat_factor = 2.0;
-
-    let grab_extant_step_size = 8.0;
-    let grab_extant_step_count = 4;
-    let grab_extant_swingable_radius = 6;
-    let grab_extant_swingable_radius_increment = 2;
-    let grab_extant_start_size = 8;
-
-    let debugdraw_grab_hbs = false;
+    let walk_accel = o.walk_accel.unwrap_or(walk_terminal_vel / 0.3);
+    let walk_friction =
+      o.walk_friction.unwrap_or(walk_terminal_vel * 60.0 / 2.0);
+    let walk_turn_accel =
+      o.walk_turn_accel.unwrap_or(walk_terminal_vel * 60.0 / 1.0);
+    let walk_overfast_friction = o
+      .walk_overfast_friction
+      .unwrap_or(walk_terminal_vel * 60.0 / 10.0);
+
+    let air_terminal_vel = o.air_terminal_vel.unwrap_or(walk_terminal_vel);
+    let air_accel = o.air_accel.unwrap_or(air_terminal_vel * 60.0 / 16.0);
+    let air_friction = o.air_friction.unwrap_or(air_terminal_vel * 60.0 / 16.0);
+    let air_turn_accel =
+      o.air_turn_accel.unwrap_or(air_terminal_vel * 60.0 / 8.0);
+    let air_overfast_friction = o
+      .air_overfast_friction
+      .unwrap_or(air_terminal_vel * 60.0 / 30.0);
+
+    let jump_height = o.jump_height.unwrap_or(40.0);
+    let time_to_jump_apex = o.time_to_jump_apex.unwrap_or(0.45);
+    let jump_impulse_vel = o
+      .jump_impulse_vel
+      .unwrap_or(2.0 * jump_height / time_to_jump_apex);
+    let jump_gravity = o
+      .jump_gravity
+      .unwrap_or(jump_impulse_vel / time_to_jump_apex);
+    let jump_release_gravity =
+      o.jump_release_gravity.unwrap_or(jump_gravity * 3.0);
+    let falling_gravity = o.falling_gravity.unwrap_or(jump_gravity * 2.5);
+    let coyote_gravity = o.coyote_gravity.unwrap_or(falling_gravity * 0.5);
+
+    let fall_terminal_vel = o.fall_terminal_vel.unwrap_or(270.0);
+    let fall_friction_y =
+      o.fall_friction_y.unwrap_or(fall_terminal_vel * 60.0 / 15.0);
+    let plummet_terminal_vel = o.plummet_terminal_vel.unwrap_or(400.0);
+    let plummet_friction_y = o
+      .plummet_friction_y
+      .unwrap_or(plummet_terminal_vel * 60.0 / 30.0);
+
+    let coyote_time = o.coyote_time.unwrap_or(0.05);
+    let jump_buffer_len = o.jump_buffer_len.unwrap_or(0.1);
+
+    let rod_anchor_dist = o.rod_anchor_dist.unwrap_or(12.0);
+    let vel_to_swing_vel_rate = o.vel_to_swing_vel_rate.unwrap_or(0.05);
+    let swing_gravity = o.swing_gravity.unwrap_or(5.0);
+    let swing_friction = o.swing_friction.unwrap_or(0.05);
+    let swing_too_far_angle = o.swing_too_far_angle.unwrap_or(TAU / 4.0);
+    let swing_too_far_gravity = o.swing_too_far_gravity.unwrap_or(10.0);
+
+    let player_swing_acc = o.player_swing_acc.unwrap_or(4.0);
+
+    let swing_terminal_vel = o.swing_terminal_vel.unwrap_or(13.0);
+    let swing_vel_to_vel_rate_y = o.swing_vel_to_vel_rate_y.unwrap_or(2.5);
+    let swing_vel_to_vel_rate_x = o
+      .swing_vel_to_vel_rate_x
+      .unwrap_or(swing_vel_to_vel_rate_y * 0.9);
+    let start_grab_speed_cheat_min =
+      o.start_grab_speed_cheat_min.unwrap_or(1.5);
+    let start_grab_speed_cheat_max =
+      o.start_grab_speed_cheat_max.unwrap_or(9.0);
+
+    let angle_to_cheat_launch_vel_at =
+      o.angle_to_cheat_launch_vel_at.unwrap_or(TAU * 0.225);
+    let angle_launch_vel_cheat_factor =
+      o.angle_launch_vel_cheat_factor.unwrap_or(2.0);
+
+    let grab_extant_step_size = o.grab_extant_step_size.unwrap_or(8.0);
+    let grab_extant_step_count = o.grab_extant_step_count.unwrap_or(4);
+    let grab_extant_swingable_radius =
+      o.grab_extant_swingable_radius.unwrap_or(6);
+    let grab_extant_swingable_radius_increment =
+      o.grab_extant_swingable_radius_increment.unwrap_or(2);
+    let grab_extant_start_size = o.grab_extant_start_size.unwrap_or(8);
+
+    let respawn_delay = o.respawn_delay.unwrap_or(0.5);
+
+    let jump_corner_correction = o.jump_corner_correction.unwrap_or(3);
+    let ledge_corner_correction = o.ledge_corner_correction.unwrap_or(3);
+
+    let debugdraw_grab_hbs = o.debugdraw_grab_hbs.unwrap_or(false);
 
     Self {
       walk_terminal_vel,
@@ -175,7 +276,26 @@ impl Default for PlayerStats {
       grab_extant_swingable_radius,
       grab_extant_swingable_radius_increment,
       grab_extant_start_size,
+      respawn_delay,
+      jump_corner_correction,
+      ledge_corner_correction,
       debugdraw_grab_hbs,
     }
   }
 }
+
+#[test]
+fn overrides() {
+  let doc: kdl::KdlDocument =
+    "player jump-height=80 coyote-time=0.1".parse().unwrap();
+  let overrides: PlayerStatsOverrides =
+    knurdy::deserialize_node(&doc.nodes()[0]).unwrap();
+  let stats = PlayerStats::new(&overrides);
+  let default = PlayerStats::default();
+
+  assert_eq!(stats.jump_height, 80.0);
+  assert_eq!(stats.coyote_time, 0.1);
+  // Derived from the new jump height
+  assert_eq!(stats.jump_impulse_vel, default.jump_impulse_vel * 2.0);
+  assert_eq!(stats.walk_accel, default.walk_accel);
+}
@@ -12,9 +12,8 @@ use crate::{
       player::{Normal, Swinging},
       KinematicState, Positioned, SwingableOn, Velocitized,
     },
-    resource::{FabCtxHolder, TreeHolder},
+    resource::{FabCtxHolder, FabberHolder, TreeHolder},
   },
-  resources::Resources,
 };
 
 impl PlayerController {
@@ -65,28 +64,29 @@ impl PlayerController {
           };
           let swingpoint = if let Some(it) = extant_swingable {
             let pos = access.query::<&Positioned>(it).unwrap().pos;
-            Some((it, vec2(pos.x as f32, pos.y as f32)))
+            Some((it, vec2(pos.x as f32, pos.y as f32), pos))
           } else if !self.deployed_rod_in_air
             && self.deployed_rod_entity.is_none()
           {
             self.deployed_rod_in_air = true;
-            let res = Resources::get();
+            let fabber = access.read_resource::<FabberHolder>().unwrap();
             let ctx = access.read_resource::<FabCtxHolder>().unwrap();
-            let e = res
-              .fabber()
+            let rod_pos = Positioned::from_vec(anchor_pos);
+            let e = fabber
+              .0
               .instantiate(
                 "immovable-rod",
-                access.lazy_spawn().with(Positioned::from_vec(anchor_pos)),
+                access.lazy_spawn().with(rod_pos),
                 &ctx.0,
               )
               .unwrap();
             self.deployed_rod_entity = Some(e);
-            Some((e, anchor_pos))
+            Some((e, anchor_pos, rod_pos.pos))
           } else {
             None
           };
 
-          if let Some((swingee, anchor_pos)) = swingpoint {
+          if let Some((swingee, anchor_pos, swingee_pos)) = swingpoint {
             let player_vel = access.query::<&Velocitized>(me).unwrap();
 
             let anchor_delta = anchor_pos
@@ -121,6 +121,7 @@ impl PlayerController {
               vel,
               anchor_pos,
               swingee,
+              swingee_pos,
             });
           }
         }
@@ -146,6 +147,18 @@ impl PlayerController {
 
     let ks = access.query::<&KinematicState>(entity).unwrap();
 
+    // Follow the swingee if it's moving. A rod we just put down won't be
+    // spawned in yet.
+    let swingee_pos = (access.liveness(swinging.swingee)
+      == EntityLiveness::Alive)
+      .then(|| access.query::<&Positioned>(swinging.swingee))
+      .flatten();
+    if let Some(pos) = swingee_pos {
+      let moved = pos.pos - swinging.swingee_pos;
+      swinging.anchor_pos += vec2(moved.x as f32, moved.y as f32);
+      swinging.swingee_pos = pos.pos;
+    }
+
     swinging.angle = (swinging.angle + PI).rem_euclid(TAU) - PI;
 
     let gravity = if swinging.angle.abs() > stats.swing_too_far_angle {
@@ -0,0 +1,619 @@
+use aglet::CoordVec;
+use ahash::{AHashMap, AHashSet};
+use broccoli::axgeom::Rect;
+use dialga::factory::ComponentFactory;
+use kdl::KdlNode;
+use macroquad::prelude as mq;
+use palkia::prelude::*;
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+
+use crate::{
+  ecm::{message::MsgDraw, resource::Camera},
+  fabctx::FabCtx,
+  geom::Hitbox,
+  gfx::{GAME_HEIGHT, GAME_WIDTH},
+  resources::Resources,
+  tileset::Tileset,
+};
+
+use super::{Collider, HasDims, Positioned, Slope};
+
+/// A grid of tiles from one [`Tileset`].
+///
+/// The entity's hitbox should cover the whole map. It's drawn a chunk at a
+/// time, and each chunk is cached to a render target the first time it's
+/// on screen.
+///
+/// This doesn't collide with anything itself; instead, colliders get spawned
+/// over the solid tiles. See [`TileColliders`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Tilemap {
+  tileset: SmolStr,
+  tile_w: i32,
+  tile_h: i32,
+  width: u32,
+  height: u32,
+  /// Row-major indices into the tileset.
+  tiles: Vec<Option<u16>>,
+
+  #[serde(skip)]
+  chunks: ChunkCache,
+}
+
+impl Tilemap {
+  /// Width and height of a chunk, in tiles.
+  const CHUNK_SIZE: u32 = 16;
+
+  /// Make an empty tilemap.
+  pub fn new(
+    tileset: SmolStr,
+    tile_w: i32,
+    tile_h: i32,
+    width: u32,
+    height: u32,
+  ) -> Self {
+    Self {
+      tileset,
+      tile_w,
+      tile_h,
+      width,
+      height,
+      tiles: vec![None; (width * height) as usize],
+      chunks: ChunkCache::default(),
+    }
+  }
+
+  /// Lay out a map drawn in ASCII, one character per tile. A newline right
+  /// after the opening quote is ignored, so the map can start on its own
+  /// line.
+  ///
+  /// Characters in `tiles` become the tile from the tileset with that name,
+  /// and spaces are left empty. Anything else gets handed to `other` with its
+  /// position in tiles, to spawn something there or complain about.
+  pub fn from_ascii(
+    map: &str,
+    tileset_name: SmolStr,
+    tileset: &Tileset,
+    tile_size: i32,
+    tiles: &AHashMap<char, SmolStr>,
+    mut other: impl FnMut(char, u32, u32) -> eyre::Result<()>,
+  ) -> eyre::Result<Tilemap> {
+    let tiles = tiles
+      .iter()
+      .map(|(ch, name)| match tileset.index_of(name) {
+        Some(idx) => Ok((*ch, idx)),
+        None => Err(eyre::eyre!(
+          "tileset {:?} has no tile {:?}",
+          tileset_name,
+          name
+        )),
+      })
+      .collect::<eyre::Result<AHashMap<_, _>>>()?;
+
+    let map = map.strip_prefix('\n').unwrap_or(map);
+    let width = map
+      .lines()
+      .map(|row| row.chars().count())
+      .max()
+      .unwrap_or(0);
+    let mut tilemap = Tilemap::new(
+      tileset_name,
+      tile_size,
+      tile_size,
+      width as u32,
+      map.lines().count() as u32,
+    );
+    for (y, row) in map.lines().enumerate() {
+      for (x, ch) in row.chars().enumerate() {
+        if ch == ' ' {
+          continue;
+        }
+        match tiles.get(&ch) {
+          Some(idx) => tilemap.set(x as u32, y as u32, Some(*idx)),
+          None => other(ch, x as u32, y as u32)?,
+        }
+      }
+    }
+    Ok(tilemap)
+  }
+
+  /// Read the legend of an ASCII map, where each key is one character.
+  pub fn parse_legend(
+    raw: AHashMap<String, SmolStr>,
+  ) -> eyre::Result<AHashMap<char, SmolStr>> {
+    let mut legend = AHashMap::new();
+    for (key, val) in raw {
+      let mut chars = key.chars();
+      match (chars.next(), chars.next()) {
+        (Some(ch), None) if ch != ' ' => {
+          legend.insert(ch, val);
+        }
+        _ => eyre::bail!(
+          "legend key {:?} must be exactly one non-space character",
+          key
+        ),
+      }
+    }
+    Ok(legend)
+  }
+
+  pub fn get(&self, x: u32, y: u32) -> Option<u16> {
+    if x >= self.width || y >= self.height {
+      return None;
+    }
+    self.tiles[(y * self.width + x) as usize]
+  }
+
+  /// Panics if the position is out of bounds.
+  pub fn set(&mut self, x: u32, y: u32, tile: Option<u16>) {
+    assert!(
+      x < self.width && y < self.height,
+      "({}, {}) is outside a {}x{} tilemap",
+      x,
+      y,
+      self.width,
+      self.height
+    );
+    self.tiles[(y * self.width + x) as usize] = tile;
+    self
+      .chunks
+      .invalidate(x / Self::CHUNK_SIZE, y / Self::CHUNK_SIZE);
+  }
+
+  /// Swap every tile that's part of an autotile for the one that fits its
+  /// neighbors.
+  ///
+  /// Tiles connect to solid tiles and to tiles from the same autotile. The
+  /// edges of the map only count as connected if the tileset says so.
+  pub fn apply_autotiles(&mut self, tileset: &Tileset) {
+    let before = self.clone();
+    for y in 0..self.height {
+      for x in 0..self.width {
+        let Some(autotile) =
+          before.get(x, y).and_then(|idx| tileset.autotile_of(idx))
+        else {
+          continue;
+        };
+        let picked = autotile.pick(|dir| {
+          let nx = x as i32 + dir.deltas().x;
+          let ny = y as i32 + dir.deltas().y;
+          if nx < 0
+            || ny < 0
+            || nx as u32 >= self.width
+            || ny as u32 >= self.height
+          {
+            return tileset.autotile_edges_connect();
+          }
+          before
+            .get(nx as u32, ny as u32)
+            .is_some_and(|idx| tileset.is_solid(idx) || autotile.contains(idx))
+        });
+        self.set(x, y, Some(picked));
+      }
+    }
+  }
+
+  /// Give the chunks this has already drawn to `other`, if it has exactly
+  /// the same tiles, so it doesn't have to draw them again.
+  pub fn hand_chunks_to(&mut self, other: &mut Tilemap) {
+    let same = self.tileset == other.tileset
+      && self.tile_w == other.tile_w
+      && self.tile_h == other.tile_h
+      && self.width == other.width
+      && self.tiles == other.tiles;
+    if same {
+      other.chunks = std::mem::take(&mut self.chunks);
+    }
+  }
+
+  /// Size of the whole map in pixels.
+  pub fn dims(&self) -> HasDims {
+    HasDims::new(
+      self.width as i32 * self.tile_w,
+      self.height as i32 * self.tile_h,
+    )
+  }
+
+  /// Hitboxes covering every tile the tileset says is solid, merged into as
+  /// few rectangles as possible, given where the top-left corner of the map
+  /// is.
+  pub fn collider_hitboxes(
+    &self,
+    tileset: &Tileset,
+    corner: CoordVec,
+  ) -> Vec<Hitbox> {
+    let mut cells = AHashSet::new();
+    for y in 0..self.height {
+      for x in 0..self.width {
+        if self.get(x, y).is_some_and(|idx| tileset.is_solid(idx)) {
+          cells.insert((x as i32, y as i32));
+        }
+      }
+    }
+
+    merge_cells(cells)
+      .into_iter()
+      .map(|(x, y, w, h)| {
+        let x = corner.x + x * self.tile_w;
+        let y = corner.y + y * self.tile_h;
+        Hitbox(Rect::new(x, x + w * self.tile_w, y, y + h * self.tile_h))
+      })
+      .collect()
+  }
+
+  /// Hitboxes of every sloped tile, one each, given where the top-left
+  /// corner of the map is.
+  pub fn slopes(
+    &self,
+    tileset: &Tileset,
+    corner: CoordVec,
+  ) -> Vec<(Hitbox, Slope)> {
+    let mut out = Vec::new();
+    for y in 0..self.height {
+      for x in 0..self.width {
+        let Some(slope) = self.get(x, y).and_then(|idx| tileset.slope(idx))
+        else {
+          continue;
+        };
+        let x = corner.x + x as i32 * self.tile_w;
+        let y = corner.y + y as i32 * self.tile_h;
+        out.push((
+          Hitbox(Rect::new(x, x + self.tile_w, y, y + self.tile_h)),
+          slope,
+        ));
+      }
+    }
+    out
+  }
+
+  fn chunk_target(
+    &mut self,
+    cx: u32,
+    cy: u32,
+    tileset: &Tileset,
+    tex: mq::Texture2D,
+  ) -> mq::RenderTarget {
+    if let Some(target) = self.chunks.0.get(&(cx, cy)) {
+      return *target;
+    }
+    let target = self.render_chunk(cx, cy, tileset, tex);
+    self.chunks.0.insert((cx, cy), target);
+    target
+  }
+
+  fn render_chunk(
+    &self,
+    cx: u32,
+    cy: u32,
+    tileset: &Tileset,
+    tex: mq::Texture2D,
+  ) -> mq::RenderTarget {
+    let w = (Self::CHUNK_SIZE as i32 * self.tile_w) as f32;
+    let h = (Self::CHUNK_SIZE as i32 * self.tile_h) as f32;
+    let target = mq::render_target(w as u32, h as u32);
+    target.texture.set_filter(mq::FilterMode::Nearest);
+
+    mq::push_camera_state();
+    mq::set_camera(&mq::Camera2D {
+      render_target: Some(target),
+      zoom: mq::vec2(w.recip() * 2.0, h.recip() * 2.0),
+      target: mq::vec2(w / 2.0, h / 2.0),
+      ..Default::default()
+    });
+    mq::clear_background(mq::Color::new(0.0, 0.0, 0.0, 0.0));
+
+    for ty in 0..Self::CHUNK_SIZE {
+      for tx in 0..Self::CHUNK_SIZE {
+        let x = cx * Self::CHUNK_SIZE + tx;
+        let y = cy * Self::CHUNK_SIZE + ty;
+        let Some(idx) = self.get(x, y) else {
+          continue;
+        };
+        mq::draw_texture_ex(
+          tex,
+          (tx as i32 * self.tile_w) as f32,
+          (ty as i32 * self.tile_h) as f32,
+          mq::WHITE,
+          mq::DrawTextureParams {
+            dest_size: Some(mq::vec2(self.tile_w as f32, self.tile_h as f32)),
+            source: Some(tileset.source_rect(idx, tex.width())),
+            ..Default::default()
+          },
+        );
+      }
+    }
+
+    mq::pop_camera_state();
+    target
+  }
+}
+
+impl Component for Tilemap {
+  fn register_handlers(builder: HandlerBuilder<Self>) -> HandlerBuilder<Self>
+  where
+    Self: Sized,
+  {
+    builder.handle_write(|this, msg: MsgDraw, me, access| {
+      let pos = access.query::<&Positioned>(me).unwrap();
+      let dims = access.query::<&HasDims>(me).unwrap();
+      let cam_center = access
+        .read_resource::<Camera>()
+        .unwrap()
+        .center_at(msg.alpha());
+
+      let assets = Resources::get();
+      let Some(tileset) = assets.get_tileset(&this.tileset) else {
+        return msg;
+      };
+      let tex = assets.get_texture(tileset.texture());
+
+      let corner = pos.pos - CoordVec::new(dims.w / 2, dims.h / 2);
+      let view_corner = cam_center
+        - CoordVec::new(GAME_WIDTH as i32 / 2, GAME_HEIGHT as i32 / 2);
+      let view = view_corner - corner;
+
+      // Figure out which chunks are on screen
+      let chunk_w = Self::CHUNK_SIZE as i32 * this.tile_w;
+      let chunk_h = Self::CHUNK_SIZE as i32 * this.tile_h;
+      let max_cx = this.width.div_ceil(Self::CHUNK_SIZE) as i32 - 1;
+      let max_cy = this.height.div_ceil(Self::CHUNK_SIZE) as i32 - 1;
+      let cx1 = view.x.div_euclid(chunk_w).max(0);
+      let cx2 = (view.x + GAME_WIDTH as i32).div_euclid(chunk_w).min(max_cx);
+      let cy1 = view.y.div_euclid(chunk_h).max(0);
+      let cy2 = (view.y + GAME_HEIGHT as i32)
+        .div_euclid(chunk_h)
+        .min(max_cy);
+
+      for cy in cy1..=cy2 {
+        for cx in cx1..=cx2 {
+          let target = this.chunk_target(cx as u32, cy as u32, tileset, tex);
+          let chunk_corner =
+            corner + CoordVec::new(cx * chunk_w, cy * chunk_h) - cam_center;
+          mq::draw_texture(
+            target.texture,
+            chunk_corner.x as f32,
+            chunk_corner.y as f32,
+            mq::WHITE,
+          );
+        }
+      }
+
+      msg
+    })
+  }
+}
+
+/// Render targets for the chunks of a [`Tilemap`] that have been drawn.
+///
+/// Cloning this gives an empty cache, because the targets can only be
+/// deleted once.
+#[derive(Debug, Default)]
+struct ChunkCache(AHashMap<(u32, u32), mq::RenderTarget>);
+
+impl ChunkCache {
+  fn invalidate(&mut self, cx: u32, cy: u32) {
+    if let Some(target) = self.0.remove(&(cx, cy)) {
+      target.delete();
+    }
+  }
+}
+
+impl Clone for ChunkCache {
+  fn clone(&self) -> Self {
+    Self::default()
+  }
+}
+
+impl Drop for ChunkCache {
+  fn drop(&mut self) {
+    for target in self.0.values() {
+      target.delete();
+    }
+  }
+}
+
+/// Factory for a [`Tilemap`] drawn with characters, plus colliders for its
+/// solid and sloped tiles.
+///
+/// The entity must already be [`Positioned`]; that's where the center of the
+/// map goes.
+pub struct TilemapFactory;
+
+impl ComponentFactory<FabCtx> for TilemapFactory {
+  fn assemble<'a, 'w>(
+    &self,
+    mut builder: EntityBuilder<'a, 'w>,
+    node: &KdlNode,
+    ctx: &FabCtx,
+  ) -> eyre::Result<EntityBuilder<'a, 'w>> {
+    #[derive(Deserialize)]
+    struct Raw {
+      tileset: SmolStr,
+      tile_size: i32,
+      /// Characters to the names of tiles in the tileset.
+      legend: AHashMap<String, SmolStr>,
+      map: String,
+    }
+
+    let raw: Raw = knurdy::deserialize_node(node)?;
+    let tileset = ctx
+      .tileset(&raw.tileset)
+      .ok_or_else(|| eyre::eyre!("no tileset named {:?}", raw.tileset))?;
+    let pos = builder
+      .get_component::<Positioned>()
+      .ok_or_else(|| eyre::eyre!("tilemaps need to be positioned"))?
+      .pos;
+
+    let legend = Tilemap::parse_legend(raw.legend)?;
+    let mut tilemap = Tilemap::from_ascii(
+      &raw.map,
+      raw.tileset.clone(),
+      tileset,
+      raw.tile_size,
+      &legend,
+      |ch, _, _| eyre::bail!("{:?} is not in the legend", ch),
+    )?;
+    tilemap.apply_autotiles(tileset);
+
+    let dims = tilemap.dims();
+    let corner = pos - CoordVec::new(dims.w / 2, dims.h / 2);
+    TileColliders::new(&tilemap, tileset, corner).spawn(&mut builder);
+
+    builder.insert(dims);
+    builder.insert(tilemap);
+    Ok(builder)
+  }
+}
+
+/// Colliders over the solid and sloped tiles of a [`Tilemap`], in world
+/// space.
+///
+/// Solid tiles get merged into as few colliders as possible; see
+/// [`Tilemap::collider_hitboxes`]. Slopes get one collider each.
+#[derive(Debug, Clone, Default)]
+pub struct TileColliders {
+  pub solid: Vec<Hitbox>,
+  pub slopes: Vec<(Hitbox, Slope)>,
+}
+
+impl TileColliders {
+  /// Figure out the colliders, given where the top-left corner of the map
+  /// is.
+  pub fn new(tilemap: &Tilemap, tileset: &Tileset, corner: CoordVec) -> Self {
+    Self {
+      solid: tilemap.collider_hitboxes(tileset, corner),
+      slopes: tilemap.slopes(tileset, corner),
+    }
+  }
+
+  /// Spawn the colliders alongside the entity being built, which is usually
+  /// the tilemap.
+  pub fn spawn(&self, builder: &mut EntityBuilder<'_, '_>) {
+    let solid = self.solid.iter().map(|hb| (*hb, None));
+    let slopes = self.slopes.iter().map(|(hb, slope)| (*hb, Some(*slope)));
+    for (hb, slope) in solid.chain(slopes) {
+      let mut collider = builder
+        .spawn_again()
+        .with(Positioned::new(hb.center()))
+        .with(HasDims::new(hb.w(), hb.h()))
+        .with(Collider::default());
+      if let Some(slope) = slope {
+        collider.insert(slope);
+      }
+      collider.build();
+    }
+  }
+}
+
+/// Greedily merge grid cells into rectangles, returned as `(x, y, w, h)`.
+///
+/// Each rectangle is grown as far right as it can go, then as far down
+/// as the whole width allows.
+fn merge_cells(mut cells: AHashSet<(i32, i32)>) -> Vec<(i32, i32, i32, i32)> {
+  let mut sorted = cells.iter().copied().collect::<Vec<_>>();
+  sorted.sort_unstable_by_key(|&(x, y)| (y, x));
+
+  let mut out = Vec::new();
+  for (x, y) in sorted {
+    if !cells.contains(&(x, y)) {
+      // already got merged into something
+      continue;
+    }
+
+    let mut w = 1;
+    while cells.contains(&(x + w, y)) {
+      w += 1;
+    }
+    let mut h = 1;
+    while (x..x + w).all(|cx| cells.contains(&(cx, y + h))) {
+      h += 1;
+    }
+
+    for cy in y..y + h {
+      for cx in x..x + w {
+        cells.remove(&(cx, cy));
+      }
+    }
+    out.push((x, y, w, h));
+  }
+  out
+}
+
+#[test]
+fn merging() {
+  let cells = [
+    (0, 0),
+    (1, 0),
+    (2, 0),
+    (0, 1),
+    (1, 1),
+    (2, 1),
+    (0, 2),
+    (5, 5),
+  ];
+  let mut rects = merge_cells(cells.into_iter().collect());
+  rects.sort();
+  assert_eq!(rects, vec![(0, 0, 3, 2), (0, 2, 1, 1), (5, 5, 1, 1)]);
+}
+
+#[test]
+fn autotile_edges() {
+  use crate::tileset::test_autotileset;
+
+  let row = |tileset: &Tileset| {
+    let mut tilemap = Tilemap::new("test".into(), 8, 8, 3, 1);
+    for x in 0..3 {
+      tilemap.set(x, 0, tileset.index_of("wall"));
+    }
+    tilemap.apply_autotiles(tileset);
+    (0..3)
+      .map(|x| tilemap.get(x, 0).unwrap())
+      .collect::<Vec<_>>()
+  };
+
+  // East, east and west, west
+  assert_eq!(row(&test_autotileset(false)), vec![2, 10, 8]);
+  // Plus north and south, and off the ends
+  assert_eq!(row(&test_autotileset(true)), vec![15, 15, 15]);
+}
+
+#[test]
+fn ascii_maps() {
+  use crate::tileset::test_autotileset;
+
+  let tileset = test_autotileset(false);
+  let legend = Tilemap::parse_legend(
+    [("#".to_owned(), SmolStr::from("w3"))]
+      .into_iter()
+      .collect(),
+  )
+  .unwrap();
+  let mut others = Vec::new();
+  let tilemap = Tilemap::from_ascii(
+    "\n# @\n ##",
+    "test".into(),
+    &tileset,
+    8,
+    &legend,
+    |ch, x, y| {
+      others.push((ch, x, y));
+      Ok(())
+    },
+  )
+  .unwrap();
+
+  assert_eq!((tilemap.width, tilemap.height), (3, 2));
+  let w3 = tileset.index_of("w3");
+  assert_eq!(tilemap.get(0, 0), w3);
+  assert_eq!(tilemap.get(1, 0), None);
+  assert_eq!(tilemap.get(2, 1), w3);
+  assert_eq!(others, vec![('@', 2, 0)]);
+
+  assert!(Tilemap::parse_legend(
+    [("##".to_owned(), SmolStr::from("w3"))]
+      .into_iter()
+      .collect()
+  )
+  .is_err());
+}
@@ -0,0 +1,76 @@
+use std::collections::BTreeSet;
+
+use palkia::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A non-solid area that notices when things move through it.
+///
+/// Anything with a [`Mover`] overlapping its hitbox is a visitor. Every tick,
+/// the trigger and each visitor get sent a [`MsgTrigger`] saying whether it
+/// just entered, is staying, or just left; put other components next to this
+/// one to do something about them.
+///
+/// [`Mover`]: crate::ecm::component::Mover
+/// [`MsgTrigger`]: crate::ecm::message::MsgTrigger
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Trigger {
+  /// What was overlapping it as of last tick. This is ordered so the
+  /// messages go out in the same order every time.
+  #[serde(default)]
+  pub visitors: BTreeSet<Entity>,
+}
+
+impl Component for Trigger {
+  fn register_handlers(builder: HandlerBuilder<Self>) -> HandlerBuilder<Self>
+  where
+    Self: Sized,
+  {
+    builder
+  }
+}
+
+#[test]
+fn checkpoint_trigger() {
+  use aglet::CoordVec;
+
+  use crate::{
+    ecm::{
+      component::{Checkpoint, HasDims, Positioned},
+      resource::RespawnPoint,
+    },
+    states::StateGameplay,
+  };
+
+  let mut state = StateGameplay::sandbox(Vec::new());
+  let player = state.player();
+  let spawn = state.player_pos();
+
+  let checkpoint_pos = spawn + CoordVec::new(2, 0);
+  let checkpoint = state
+    .world_mut()
+    .spawn()
+    .with(Positioned::new(checkpoint_pos))
+    .with(HasDims::new(8, 16))
+    .with(Trigger::default())
+    .with(Checkpoint)
+    .build();
+  let visitors = |state: &StateGameplay| {
+    let trigger = state.world().query::<&Trigger>(checkpoint).unwrap();
+    trigger.visitors.clone()
+  };
+
+  state.run(1);
+  assert_eq!(
+    state.world().read_resource::<RespawnPoint>().unwrap().0,
+    checkpoint_pos
+  );
+  assert!(visitors(&state).contains(&player));
+
+  state
+    .world_mut()
+    .query::<&mut Positioned>(player)
+    .unwrap()
+    .pos = spawn + CoordVec::new(0, -40);
+  state.run(1);
+  assert!(visitors(&state).is_empty());
+}
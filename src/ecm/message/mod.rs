@@ -1,4 +1,4 @@
-use aglet::Direction8;
+use aglet::{CoordVec, Direction8};
 
 use palkia::prelude::*;
 
@@ -20,10 +20,52 @@ impl MsgPhysicsTick {
 }
 impl Message for MsgPhysicsTick {}
 
-#[derive(Debug, Clone, Default)]
-pub struct MsgDraw {}
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MsgDraw {
+  alpha: f32,
+}
+impl MsgDraw {
+  pub fn new(alpha: f32) -> Self {
+    Self { alpha }
+  }
+  /// How far between the last tick and the next one this frame is, from 0 to
+  /// 1. Draw things at [`Positioned::pos_at`] this to keep them smooth.
+  ///
+  /// [`Positioned::pos_at`]: crate::ecm::component::Positioned::pos_at
+  pub fn alpha(&self) -> f32 {
+    self.alpha
+  }
+}
 impl Message for MsgDraw {}
 
+/// Sent to things that touched a [`Hazard`], or got squished by a moving
+/// solid with nowhere to go. Only the player cares.
+///
+/// [`Hazard`]: crate::ecm::component::Hazard
+#[derive(Debug, Clone, Copy)]
+pub struct MsgKill;
+impl Message for MsgKill {}
+
+/// Sent to the player when they come back to life at the last checkpoint.
+///
+/// Anything on the player that remembers what was going on before should
+/// forget it.
+#[derive(Debug, Clone, Copy)]
+pub struct MsgRespawn {
+  pos: CoordVec,
+}
+impl Message for MsgRespawn {}
+impl MsgRespawn {
+  pub fn new(pos: CoordVec) -> Self {
+    Self { pos }
+  }
+
+  /// Where the player is coming back.
+  pub fn pos(&self) -> CoordVec {
+    self.pos
+  }
+}
+
 /// Sent to colliders when an entity hits it.
 ///
 /// The normal is the direction it is getting hit from,
@@ -72,3 +114,48 @@ impl MsgSendHit {
     self.normal
   }
 }
+
+/// Sent to a [`Trigger`] and to the thing visiting it every tick they
+/// overlap, and once more on the tick they stop.
+///
+/// If the visitor was despawned, only the trigger gets told it left.
+///
+/// [`Trigger`]: crate::ecm::component::Trigger
+#[derive(Debug, Clone, Copy)]
+pub struct MsgTrigger {
+  trigger: Entity,
+  visitor: Entity,
+  phase: TriggerPhase,
+}
+impl Message for MsgTrigger {}
+impl MsgTrigger {
+  pub fn new(trigger: Entity, visitor: Entity, phase: TriggerPhase) -> Self {
+    Self {
+      trigger,
+      visitor,
+      phase,
+    }
+  }
+
+  pub fn trigger(&self) -> Entity {
+    self.trigger
+  }
+
+  pub fn visitor(&self) -> Entity {
+    self.visitor
+  }
+
+  pub fn phase(&self) -> TriggerPhase {
+    self.phase
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerPhase {
+  /// The first tick they overlap.
+  Enter,
+  /// Every tick after that while they still overlap.
+  Stay,
+  /// The first tick they don't.
+  Exit,
+}
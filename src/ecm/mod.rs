@@ -4,12 +4,13 @@ pub mod actions;
 pub mod component;
 pub mod message;
 pub mod resource;
+pub mod snapshot;
 
 use component::*;
 
 use crate::EntityFab;
 
-use self::resource::{Camera, HitboxTracker};
+use self::resource::{Camera, HitboxTracker, TreeHolder};
 
 /// Register components and insert resources
 pub fn setup_world(world: &mut World) {
@@ -21,7 +22,9 @@ pub fn setup_world(world: &mut World) {
 
   world.register_component::<FrictionHaver>();
   world.register_component::<Bonker>();
+  world.register_component::<Actor>();
   world.register_component::<Collider>();
+  world.register_component::<Slope>();
 
   world.register_component::<AgeTracker>();
   world.register_component::<LimitedTimeOffer>();
@@ -29,24 +32,37 @@ pub fn setup_world(world: &mut World) {
   world.register_component::<ZLevel>();
   world.register_component::<ColoredHitbox>();
   world.register_component::<DrawTexture>();
+  world.register_component::<AutotileSprite>();
+  world.register_component::<AnimatedSprite>();
+  world.register_component::<Tilemap>();
 
   world.register_component::<PlayerController>();
+  world.register_component::<PlayerAnimator>();
 
   world.register_component::<SwingableOn>();
+  world.register_component::<Trigger>();
+  world.register_component::<Checkpoint>();
+  world.register_component::<Hazard>();
+  world.register_component::<PathFollower>();
+  world.register_component::<AttachedTo>();
 
   //
 
   world.insert_resource(Camera::new());
   world.insert_resource_default::<HitboxTracker>();
+  world.insert_resource_default::<TreeHolder>();
 }
 
 pub fn setup_fabber(fab: &mut EntityFab) {
   // dims, mover, vel
   fab.register("physic-body", PhysicFactory);
+  fab.register_serde::<HasDims>("dimensions");
 
   fab.register_serde::<Bonker>("bonker"); // but here's the bonker
+  fab.register_serde::<Actor>("actor");
   fab.register("friction", FrictionFactory);
   fab.register_serde::<Collider>("collider");
+  fab.register_serde::<Slope>("slope");
 
   fab.register_serde::<AgeTracker>("age-tracker");
   fab.register_serde::<LimitedTimeOffer>("despawn-timer");
@@ -54,8 +70,16 @@ pub fn setup_fabber(fab: &mut EntityFab) {
   fab.register_serde::<ZLevel>("zlevel");
   fab.register_serde::<ColoredHitbox>("colored-hitbox");
   fab.register_serde::<DrawTexture>("texture");
+  fab.register_serde::<AutotileSprite>("autotile");
+  fab.register_serde::<AnimatedSprite>("animated-sprite");
+  fab.register("tilemap", TilemapFactory);
 
   fab.register_serde::<SwingableOn>("swingable-on");
+  fab.register_serde::<Trigger>("trigger");
+  fab.register_serde::<Checkpoint>("checkpoint");
+  fab.register_serde::<Hazard>("hazard");
+  fab.register("path", PathFollowerFactory);
 
   fab.register("player", PlayerFactory);
+  fab.register("player-anims", PlayerAnimFactory);
 }
@@ -1,12 +1,19 @@
 use aglet::{Area, CoordVec};
 use palkia::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::gfx::{GAME_HEIGHT, GAME_WIDTH};
+use crate::{
+    geom::lerp_coords,
+    gfx::{GAME_HEIGHT, GAME_WIDTH},
+};
 
 /// Where the world is viewed from
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Camera {
     current: CoordVec,
+    /// Where it was before the last update, to draw from between there and
+    /// here.
+    prev: CoordVec,
 
     bb_corner: CoordVec,
     bb_size: CoordVec,
@@ -19,12 +26,14 @@ impl Camera {
     pub fn new() -> Self {
         Self {
             current: CoordVec::new(0, 0),
+            prev: CoordVec::new(0, 0),
             bb_corner: CoordVec::new(-160, -10_000),
             bb_size: CoordVec::new(320, 20_000),
         }
     }
 
     pub fn update(&mut self, player_pos: CoordVec) {
+        self.prev = self.current;
         for (slot, player, corner, size, window_size) in [
             (
                 &mut self.current.x,
@@ -50,7 +59,9 @@ impl Camera {
         }
     }
 
-    pub fn center(&self) -> CoordVec {
-        self.current
+    /// Where to draw from, `alpha` of the way from before the last update to
+    /// now.
+    pub fn center_at(&self, alpha: f32) -> CoordVec {
+        lerp_coords(self.prev, self.current, alpha)
     }
 }
@@ -4,13 +4,36 @@ mod phys;
 pub use camera::*;
 pub use phys::*;
 
+use std::sync::Arc;
+
+use aglet::CoordVec;
 use palkia::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::fabctx::FabCtx;
+use crate::{controls::ControlState, fabctx::FabCtx, EntityFab};
 
 pub struct FabCtxHolder(pub FabCtx);
 impl Resource for FabCtxHolder {}
 
+/// The blueprints, for spawning things mid-game without going through
+/// [`Resources`](crate::resources::Resources).
+pub struct FabberHolder(pub Arc<EntityFab>);
+impl Resource for FabberHolder {}
+
+/// The controls for this tick, sampled once at the start of it.
+#[derive(Default)]
+pub struct TheControls(pub ControlState);
+impl Resource for TheControls {}
+
 /// Holder for the player!
+#[derive(Serialize, Deserialize)]
 pub struct ThePlayerEntity(pub Entity);
 impl Resource for ThePlayerEntity {}
+
+/// Where the player comes back when they die. This starts at the level's
+/// player spawn, and moves to each [`Checkpoint`] they touch.
+///
+/// [`Checkpoint`]: crate::ecm::component::Checkpoint
+#[derive(Serialize, Deserialize)]
+pub struct RespawnPoint(pub CoordVec);
+impl Resource for RespawnPoint {}
@@ -1,53 +1,142 @@
 use std::{collections::hash_set, iter};
 
-use ahash::AHashSet;
-use broccoli::{aabb::pin::AabbPin, axgeom::Rect, Tree, TreeData};
+use ahash::{AHashMap, AHashSet};
+use broccoli::{aabb::pin::AabbPin, Tree, TreeData};
 use palkia::prelude::*;
 
 use crate::geom::{EntityAABB, Hitbox};
 
-/// Keeps track of everything with both a [`Positioned`] and [`HasDims`]
+/// Keeps track of everything with both a [`Positioned`] and [`HasDims`].
+///
+/// Things with a [`Mover`] are assumed to move around; everything else is
+/// assumed to stay exactly where it was spawned. Debug builds check that
+/// every tick, when the static tree gets rebuilt.
+///
+/// [`Positioned`]: crate::ecm::component::Positioned
+/// [`HasDims`]: crate::ecm::component::HasDims
+/// [`Mover`]: crate::ecm::component::Mover
 #[derive(Debug, Default)]
 pub struct HitboxTracker {
-  es: AHashSet<Entity>,
+  statics: AHashMap<Entity, Hitbox>,
+  movers: AHashSet<Entity>,
+  /// Bumped whenever the set of statics changes.
+  static_generation: u64,
 }
 impl Resource for HitboxTracker {}
 
 impl HitboxTracker {
-  pub fn on_create(&mut self, e: Entity) {
-    self.es.insert(e);
+  pub fn on_create(&mut self, e: Entity, hb: Hitbox, is_mover: bool) {
+    if is_mover {
+      self.movers.insert(e);
+    } else {
+      self.statics.insert(e, hb);
+      self.static_generation += 1;
+    }
   }
 
   pub fn on_remove(&mut self, e: Entity) {
-    self.es.remove(&e);
+    self.movers.remove(&e);
+    if self.statics.remove(&e).is_some() {
+      self.static_generation += 1;
+    }
   }
 
-  pub fn iter(&self) -> iter::Copied<hash_set::Iter<'_, Entity>> {
-    self.es.iter().copied()
+  pub fn movers(&self) -> iter::Copied<hash_set::Iter<'_, Entity>> {
+    self.movers.iter().copied()
+  }
+
+  /// Everything that isn't a mover, and where it was spawned.
+  pub fn statics(&self) -> impl Iterator<Item = (Entity, Hitbox)> + '_ {
+    self.statics.iter().map(|(e, hb)| (*e, *hb))
   }
 
   pub fn contains(&self, e: Entity) -> bool {
-    self.es.contains(&e)
+    self.movers.contains(&e) || self.statics.contains_key(&e)
   }
 }
 
-/// Janky-ass Broccoli tree holder
+/// Spatial index over everything in the [`HitboxTracker`].
+///
+/// Statics live in a Broccoli tree that only gets rebuilt when one has been
+/// spawned or despawned, at most once a tick. Movers live in a spatial hash
+/// that gets updated as they move around.
 pub struct TreeHolder {
-  bots: Vec<EntityAABB>,
-  data: TreeData<f64>,
+  static_bots: Vec<EntityAABB>,
+  static_data: TreeData<f64>,
+  /// The [`HitboxTracker::static_generation`] the tree was built from.
+  static_generation: Option<u64>,
+
+  movers: SpatialHash,
 }
 impl Resource for TreeHolder {}
 
+impl Default for TreeHolder {
+  fn default() -> Self {
+    let mut static_bots = Vec::new();
+    let static_data = Tree::new(&mut static_bots).get_tree_data();
+    Self {
+      static_bots,
+      static_data,
+      static_generation: None,
+      movers: SpatialHash::default(),
+    }
+  }
+}
+
 impl TreeHolder {
-  pub fn new(data: TreeData<f64>, bots: Vec<EntityAABB>) -> Self {
-    Self { bots, data }
+  /// Rebuild the static tree if the set of statics has changed.
+  ///
+  /// This is the expensive part, so it's done all at once for everything
+  /// spawned or despawned over a whole tick.
+  pub fn rebuild_statics(&mut self, tracker: &HitboxTracker) {
+    if self.static_generation != Some(tracker.static_generation) {
+      self.static_bots = tracker
+        .statics
+        .iter()
+        .map(|(e, hb)| EntityAABB::new(*e, *hb))
+        .collect();
+      let tree = Tree::new(&mut self.static_bots);
+      #[cfg(debug_assertions)]
+      broccoli::assert::assert_tree_invariants(&tree);
+      self.static_data = tree.get_tree_data();
+      self.static_generation = Some(tracker.static_generation);
+    }
   }
 
-  pub fn get_tree(&mut self) -> Tree<'_, EntityAABB> {
-    let tree = Tree::from_tree_data(&mut self.bots, &self.data);
-    #[cfg(debug_assertions)]
-    broccoli::assert::assert_tree_invariants(&tree);
-    tree
+  /// Forget about any movers that have been removed.
+  ///
+  /// This doesn't update the positions of movers; use
+  /// [`TreeHolder::update_mover`] for that.
+  pub fn sync_movers(&mut self, tracker: &HitboxTracker) {
+    self.movers.retain(|e| tracker.movers.contains(&e));
+  }
+
+  /// Tell the index where a mover is now. This is cheap if it hasn't moved.
+  pub fn update_mover(&mut self, e: Entity, hb: Hitbox) {
+    self.movers.update(e, hb);
+  }
+
+  /// The tree [`TreeHolder::rebuild_statics`] built. This only puts the
+  /// bots back into the nodes it sorted them into, so it's cheap enough to
+  /// do for every query.
+  fn get_static_tree(&mut self) -> Tree<'_, EntityAABB> {
+    Tree::from_tree_data(&mut self.static_bots, &self.static_data)
+  }
+
+  /// Call the closure with everything whose hitbox intersects the given one.
+  pub fn for_each_in_box(
+    &mut self,
+    hitbox: Hitbox,
+    mut f: impl FnMut(Entity, Hitbox),
+  ) {
+    let mut tree = self.get_static_tree();
+    tree.find_all_intersect_rect(
+      AabbPin::new(&mut (hitbox.0.inner_as(), ())),
+      |_, hit| f(hit.e, hit.hb()),
+    );
+    drop(tree);
+
+    self.movers.for_each_in_box(hitbox, f);
   }
 
   pub fn get_entities_in_box(
@@ -55,19 +144,153 @@ impl TreeHolder {
     hitbox: Hitbox,
     filter: impl Fn(Entity) -> bool,
   ) -> Vec<Entity> {
-    let mut tree = self.get_tree();
-
     let mut out = Vec::new();
-    tree.find_all_intersect_rect(
-      AabbPin::new(&mut (hitbox.0.inner_as(), ())),
-      |_, hit| {
-        let e = hit.e;
-        if filter(e) {
-          out.push(e);
+    self.for_each_in_box(hitbox, |e, _| {
+      if filter(e) {
+        out.push(e);
+      }
+    });
+    out
+  }
+}
+
+/// Buckets hitboxes by which cells of a coarse grid they touch.
+#[derive(Debug, Default)]
+struct SpatialHash {
+  cells: AHashMap<(i32, i32), Vec<Entity>>,
+  hitboxes: AHashMap<Entity, Hitbox>,
+}
+
+impl SpatialHash {
+  const CELL_SIZE: i32 = 64;
+
+  fn update(&mut self, e: Entity, hb: Hitbox) {
+    match self.hitboxes.insert(e, hb) {
+      Some(old) if old.0 == hb.0 => return,
+      Some(old) => self.unbucket(e, old),
+      None => {}
+    }
+    for cell in Self::cells_of(hb) {
+      self.cells.entry(cell).or_default().push(e);
+    }
+  }
+
+  fn retain(&mut self, mut keep: impl FnMut(Entity) -> bool) {
+    let dead = self
+      .hitboxes
+      .keys()
+      .copied()
+      .filter(|e| !keep(*e))
+      .collect::<Vec<_>>();
+    for e in dead {
+      let hb = self.hitboxes.remove(&e).unwrap();
+      self.unbucket(e, hb);
+    }
+  }
+
+  fn unbucket(&mut self, e: Entity, hb: Hitbox) {
+    for cell in Self::cells_of(hb) {
+      if let Some(bucket) = self.cells.get_mut(&cell) {
+        bucket.retain(|other| *other != e);
+        if bucket.is_empty() {
+          self.cells.remove(&cell);
         }
-      },
-    );
+      }
+    }
+  }
+
+  fn for_each_in_box(&self, hitbox: Hitbox, mut f: impl FnMut(Entity, Hitbox)) {
+    let mut seen = AHashSet::new();
+    for cell in Self::cells_of(hitbox) {
+      let Some(bucket) = self.cells.get(&cell) else {
+        continue;
+      };
+      for e in bucket.iter() {
+        let hb = self.hitboxes[e];
+        if hb.intersects(&hitbox) && seen.insert(*e) {
+          f(*e, hb);
+        }
+      }
+    }
+  }
+
+  fn cells_of(hb: Hitbox) -> impl Iterator<Item = (i32, i32)> {
+    let x1 = hb.x().div_euclid(Self::CELL_SIZE);
+    let x2 = (hb.x() + hb.w()).div_euclid(Self::CELL_SIZE);
+    let y1 = hb.y().div_euclid(Self::CELL_SIZE);
+    let y2 = (hb.y() + hb.h()).div_euclid(Self::CELL_SIZE);
+    (y1..=y2).flat_map(move |y| (x1..=x2).map(move |x| (x, y)))
+  }
+}
 
+#[test]
+fn spatial_hash() {
+  let mut world = World::new();
+  let [a, b] = [(); 2].map(|_| world.spawn().build());
+  let mut hash = SpatialHash::default();
+  let find = |hash: &SpatialHash, x, y| {
+    let mut out = Vec::new();
+    hash.for_each_in_box(Hitbox::new(x, y, 4, 4), |e, _| out.push(e));
     out
+  };
+
+  // Straddling the corner of four cells, either side of zero
+  hash.update(a, Hitbox::new(0, 0, 8, 8));
+  for (x, y) in [(-3, -3), (3, -3), (-3, 3), (3, 3)] {
+    assert_eq!(find(&hash, x, y), vec![a], "at ({}, {})", x, y);
   }
+  assert!(find(&hash, -8, 0).is_empty());
+
+  // Moving out of cells it used to be in
+  let cell = SpatialHash::CELL_SIZE;
+  hash.update(a, Hitbox::new(cell, 4, 8, 8));
+  assert!(find(&hash, -3, -3).is_empty());
+  assert_eq!(find(&hash, cell - 3, 4), vec![a]);
+  assert_eq!(find(&hash, cell + 3, 4), vec![a]);
+
+  // Only reported once, even though it's in several cells
+  hash.update(b, Hitbox::new(cell * 3, cell * 3, cell * 2, cell * 2));
+  let mut seen = Vec::new();
+  hash.for_each_in_box(Hitbox::new(cell, cell, cell * 4, cell * 4), |e, _| {
+    seen.push(e)
+  });
+  assert_eq!(seen.len(), 2);
+
+  hash.retain(|e| e != a);
+  assert!(find(&hash, cell + 3, 4).is_empty());
+  assert_eq!(find(&hash, cell * 3, cell * 3), vec![b]);
+  assert!(hash.cells.values().all(|bucket| !bucket.contains(&a)));
+}
+
+#[test]
+fn tree_holder() {
+  let mut world = World::new();
+  let [wall, floor, mover] = [(); 3].map(|_| world.spawn().build());
+  let mut tracker = HitboxTracker::default();
+  let mut trees = TreeHolder::default();
+  let find = |trees: &mut TreeHolder, hb| {
+    let mut out = trees.get_entities_in_box(hb, |_| true);
+    out.sort();
+    out
+  };
+
+  tracker.on_create(wall, Hitbox::new(-40, 0, 8, 64), false);
+  tracker.on_create(floor, Hitbox::new(0, 36, 200, 8), false);
+  tracker.on_create(mover, Hitbox::new(0, 0, 8, 8), true);
+  // Nothing's in there until it gets rebuilt
+  assert!(find(&mut trees, Hitbox::new(-40, 0, 4, 4)).is_empty());
+
+  trees.rebuild_statics(&tracker);
+  trees.sync_movers(&tracker);
+  trees.update_mover(mover, Hitbox::new(-36, 36, 8, 8));
+  let mut expected = vec![wall, floor, mover];
+  expected.sort();
+  assert_eq!(find(&mut trees, Hitbox::new(-38, 33, 4, 4)), expected);
+  assert_eq!(find(&mut trees, Hitbox::new(90, 40, 4, 4)), vec![floor]);
+
+  tracker.on_remove(wall);
+  tracker.on_remove(mover);
+  trees.rebuild_statics(&tracker);
+  trees.sync_movers(&tracker);
+  assert_eq!(find(&mut trees, Hitbox::new(-38, 33, 4, 4)), vec![floor]);
 }
@@ -0,0 +1,251 @@
+//! Saving and restoring whole worlds, for checkpoints, save games and bug
+//! repros.
+
+use std::{fs, path::Path};
+
+use ahash::AHashMap;
+use palkia::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+  component::*,
+  resource::{
+    Camera, HitboxTracker, RespawnPoint, ThePlayerEntity, TreeHolder,
+  },
+};
+
+/// A saved copy of every entity and the resources that matter, as
+/// MessagePack.
+///
+/// The entity allocator gets saved too, so entities come back with the same
+/// IDs, and references between them (like what the player is swinging on)
+/// keep pointing at the right things.
+///
+/// Resources that can't be saved, like the blueprints, are left as they are
+/// in the world being restored into.
+#[derive(Debug, Clone)]
+pub struct Snapshot(Vec<u8>);
+
+impl Snapshot {
+  pub fn take(world: &mut World) -> eyre::Result<Snapshot> {
+    let mut bytes = Vec::new();
+    let mut ser = rmp_serde::Serializer::new(&mut bytes).with_struct_map();
+    world.serialize(SnapshotInstrs, &mut ser)?;
+    Ok(Snapshot(bytes))
+  }
+
+  /// Replace everything in the world with what's in the snapshot.
+  ///
+  /// If the snapshot can't be read, the world is left as it was.
+  pub fn restore(&self, world: &mut World) -> eyre::Result<()> {
+    // These are rebuilt by the creation callbacks as the entities come back
+    let old_tracker = world.insert_resource_default::<HitboxTracker>();
+    let old_trees = world.insert_resource_default::<TreeHolder>();
+
+    // Tilemaps nearly always come back unchanged, so hang on to the chunks
+    // they've drawn
+    let mut tilemaps = world
+      .entities()
+      .filter_map(|e| {
+        let mut tilemap = world.query::<&mut Tilemap>(e)?;
+        Some((e, std::mem::take(&mut *tilemap)))
+      })
+      .collect::<AHashMap<_, _>>();
+
+    let mut de = rmp_serde::Deserializer::from_read_ref(&self.0);
+    if let Err(err) = world.deserialize(SnapshotInstrs, &mut de) {
+      // It reads everything before it touches the world, so the entities
+      // are all still there; put back what was taken out of it
+      if let Some(tracker) = old_tracker {
+        world.insert_resource(tracker);
+      }
+      if let Some(trees) = old_trees {
+        world.insert_resource(trees);
+      }
+      for (e, old) in tilemaps {
+        *world.query::<&mut Tilemap>(e).unwrap() = old;
+      }
+      return Err(err.into());
+    }
+
+    for e in world.entities() {
+      if let (Some(old), Some(mut tilemap)) =
+        (tilemaps.get_mut(&e), world.query::<&mut Tilemap>(e))
+      {
+        old.hand_chunks_to(&mut tilemap);
+      }
+    }
+    Ok(())
+  }
+
+  pub fn load(path: impl AsRef<Path>) -> eyre::Result<Snapshot> {
+    Ok(Snapshot(fs::read(path)?))
+  }
+
+  pub fn save(&self, path: impl AsRef<Path>) -> eyre::Result<()> {
+    if let Some(dir) = path.as_ref().parent() {
+      fs::create_dir_all(dir)?;
+    }
+    fs::write(path, &self.0)?;
+    Ok(())
+  }
+}
+
+/// Lists the components and resources to save, using their type names as
+/// keys. Keys are saved as names, so these can be reordered freely, but
+/// renaming one breaks old snapshots.
+macro_rules! snapshot_instrs {
+  (
+    components { $($cmp:ident),* $(,)? }
+    resources { $($res:ident),* $(,)? }
+  ) => {
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    enum ComponentKey {
+      $($cmp,)*
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    enum ResourceKey {
+      $($res,)*
+    }
+
+    struct SnapshotInstrs;
+
+    impl WorldSerdeInstructions<ResourceKey, ComponentKey> for SnapshotInstrs {
+      fn serialize_entity<S: serde::Serializer>(
+        &self,
+        mut ctx: EntitySerContext<'_, '_, ComponentKey, S>,
+      ) -> Result<(), S::Error> {
+        $(ctx.try_serialize::<$cmp>(ComponentKey::$cmp)?;)*
+        Ok(())
+      }
+
+      fn component_count(&self, e: Entity, world: &World) -> Option<usize> {
+        let mut count = 0;
+        $(count += world.query::<&$cmp>(e).is_some() as usize;)*
+        Some(count)
+      }
+
+      fn deserialize_entity<'a, 'de, M: serde::de::MapAccess<'de>>(
+        &'a self,
+        ctx: &mut EntityDeContext<'_, 'de, M, ComponentKey>,
+      ) -> Result<(), M::Error>
+      where
+        'de: 'a,
+      {
+        match ctx.key() {
+          $(ComponentKey::$cmp => ctx.accept::<$cmp>(),)*
+        }
+      }
+
+      fn serialize_resource<S: serde::Serializer>(
+        &self,
+        mut ctx: ResourceSerContext<'_, '_, ResourceKey, S>,
+      ) -> Result<(), S::Error> {
+        $(ctx.try_serialize::<$res>(ResourceKey::$res)?;)*
+        Ok(())
+      }
+
+      fn resource_count(&self, world: &World) -> Option<usize> {
+        let mut count = 0;
+        $(count += world.contains_resource::<$res>() as usize;)*
+        Some(count)
+      }
+
+      fn deserialize_resource<'a, 'de, M: serde::de::MapAccess<'de>>(
+        &'a self,
+        ctx: &mut ResourceDeContext<'_, 'de, M, ResourceKey>,
+      ) -> Result<(), M::Error>
+      where
+        'de: 'a,
+      {
+        match ctx.key() {
+          $(ResourceKey::$res => ctx.accept::<$res>(),)*
+        }
+      }
+    }
+  };
+}
+
+// Keep this in sync with `setup_world`
+snapshot_instrs! {
+  components {
+    Positioned,
+    HasDims,
+    Mover,
+    Velocitized,
+    KinematicState,
+    FrictionHaver,
+    Bonker,
+    Actor,
+    Collider,
+    Slope,
+    AgeTracker,
+    LimitedTimeOffer,
+    ZLevel,
+    ColoredHitbox,
+    DrawTexture,
+    AutotileSprite,
+    AnimatedSprite,
+    Tilemap,
+    PlayerController,
+    PlayerAnimator,
+    SwingableOn,
+    Trigger,
+    Checkpoint,
+    Hazard,
+    PathFollower,
+    AttachedTo,
+  }
+  resources {
+    Camera,
+    ThePlayerEntity,
+    RespawnPoint,
+  }
+}
+
+#[test]
+fn snapshot_roundtrip() {
+  use crate::states::StateGameplay;
+
+  let ticks = StateGameplay::swing_script();
+  let swinging = ticks.len();
+  let mut state = StateGameplay::sandbox(ticks);
+  state.run(swinging);
+
+  let player = state.player();
+  let saved_pos = state.player_pos();
+  let snapshot = state.snapshot().unwrap();
+
+  // Let go of the rod and fall for a while
+  state.run(60);
+  assert_ne!(state.player_pos(), saved_pos);
+
+  state.restore(&snapshot).unwrap();
+  assert_eq!(state.player(), player);
+  assert_eq!(state.player_pos(), saved_pos);
+  let world = state.world();
+  let controller = world.query::<&PlayerController>(player).unwrap();
+  let PlayerState::Swinging(swinging) = controller.state() else {
+    panic!("should be swinging again");
+  };
+  assert!(world.query::<&SwingableOn>(swinging.swingee).is_some());
+}
+
+#[test]
+fn restore_garbage() {
+  use crate::states::StateGameplay;
+
+  let mut state = StateGameplay::sandbox(Vec::new());
+  let garbage = Snapshot(b"definitely not a world".to_vec());
+  assert!(state.restore(&garbage).is_err());
+
+  // Everything's still there, and still collides
+  state.run(60);
+  assert_eq!(state.player_pos(), aglet::CoordVec::new(0, 26));
+  let world = state.world();
+  assert!(world
+    .entities()
+    .filter_map(|e| world.query::<&Tilemap>(e))
+    .all(|tilemap| tilemap.dims().w > 0));
+}
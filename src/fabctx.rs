@@ -1 +1,56 @@
-pub struct FabCtx {}
+use std::sync::Arc;
+
+use aglet::CoordVec;
+use ahash::AHashMap;
+use kdl::KdlValue;
+use smol_str::SmolStr;
+
+use crate::{sprite::SpriteSheet, tileset::Tileset};
+
+/// Context handed to every factory when instantiating a blueprint.
+#[derive(Debug, Clone, Default)]
+pub struct FabCtx {
+  /// Per-instance properties, like the custom properties on a Tiled object.
+  pub props: AHashMap<SmolStr, KdlValue>,
+  /// Per-instance waypoints in world space, for things that move along a
+  /// path. Empty if the level didn't give one.
+  pub path: Vec<CoordVec>,
+  /// Shared with [`Resources`](crate::resources::Resources), which is
+  /// locked while blueprints get instantiated.
+  pub tilesets: Arc<AHashMap<SmolStr, Tileset>>,
+  /// Also shared with [`Resources`](crate::resources::Resources), so
+  /// animations can be stepped without it.
+  pub sprite_sheets: Arc<AHashMap<SmolStr, SpriteSheet>>,
+}
+
+impl FabCtx {
+  pub fn new(
+    tilesets: Arc<AHashMap<SmolStr, Tileset>>,
+    sprite_sheets: Arc<AHashMap<SmolStr, SpriteSheet>>,
+  ) -> Self {
+    Self {
+      tilesets,
+      sprite_sheets,
+      ..Default::default()
+    }
+  }
+
+  /// Copy of this context with the given per-instance properties.
+  pub fn with_props(&self, props: AHashMap<SmolStr, KdlValue>) -> Self {
+    let mut out = self.clone();
+    out.props = props;
+    out
+  }
+
+  pub fn prop(&self, key: &str) -> Option<&KdlValue> {
+    self.props.get(key)
+  }
+
+  pub fn tileset(&self, name: &str) -> Option<&Tileset> {
+    self.tilesets.get(name)
+  }
+
+  pub fn sprite_sheet(&self, name: &str) -> Option<&SpriteSheet> {
+    self.sprite_sheets.get(name)
+  }
+}
@@ -2,7 +2,7 @@ use aglet::CoordVec;
 
 use broccoli::{
   aabb::{Aabb, ManySwap},
-  axgeom::{Rect, Vec2 as BrocVec2},
+  axgeom::Rect,
 };
 use palkia::prelude::*;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
@@ -43,7 +43,17 @@ impl Hitbox {
   }
 
   pub fn shifted_by(&self, dx: i32, dy: i32) -> Hitbox {
-    Hitbox::new(self.x() + dx, self.y() + dy, self.w(), self.h())
+    Hitbox(Rect::new(
+      self.0.x.start + dx,
+      self.0.x.end + dx,
+      self.0.y.start + dy,
+      self.0.y.end + dy,
+    ))
+  }
+
+  /// Just touching doesn't count, same as Broccoli.
+  pub fn intersects(&self, other: &Hitbox) -> bool {
+    self.0.get_intersect_rect(&other.0).is_some()
   }
 }
 
@@ -109,6 +119,13 @@ impl Aabb for EntityAABB {
 
 impl ManySwap for EntityAABB {}
 
+/// Go `alpha` of the way from `from` to `to`, rounded to the nearest pixel.
+pub fn lerp_coords(from: CoordVec, to: CoordVec, alpha: f32) -> CoordVec {
+  let lerp =
+    |a: i32, b: i32| (a as f32 + (b - a) as f32 * alpha).round() as i32;
+  CoordVec::new(lerp(from.x, to.x), lerp(from.y, to.y))
+}
+
 /// turns out signum returns 1.0 for +0.0
 pub fn signum0(x: f32) -> f32 {
   if x == 0.0 {
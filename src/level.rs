@@ -0,0 +1,294 @@
+mod tiled;
+
+use std::path::Path;
+
+use aglet::CoordVec;
+use ahash::AHashMap;
+use dialga::InstantiationError;
+use kdl::{KdlDocument, KdlNode, KdlValue};
+use palkia::prelude::*;
+use serde::Deserialize;
+use smol_str::SmolStr;
+
+use crate::{
+  ecm::component::{
+    AttachedTo, AutotileSprite, Mover, Positioned, TileColliders, Tilemap,
+    ZLevel,
+  },
+  fabctx::FabCtx,
+  tileset::Tileset,
+  EntityFab,
+};
+
+/// A level loaded from `resources/levels/`.
+///
+/// This is agnostic to the format it was loaded from; see [`Level::load_kdl`]
+/// and [`Level::load_tiled`].
+#[derive(Debug)]
+pub struct Level {
+  player_spawn: CoordVec,
+  spawns: Vec<LevelSpawn>,
+  tilemaps: Vec<LevelTilemap>,
+}
+
+/// A blueprint to instantiate at a point.
+#[derive(Debug)]
+struct LevelSpawn {
+  blueprint: SmolStr,
+  pos: CoordVec,
+  /// Fed into the [`FabCtx`] when instantiating.
+  props: AHashMap<SmolStr, KdlValue>,
+  /// Waypoints in world space, also for the [`FabCtx`].
+  path: Vec<CoordVec>,
+  /// Spawned after this, and kept at the same offset from it as they start
+  /// at. See [`AttachedTo`].
+  attached: Vec<LevelSpawn>,
+}
+
+/// A [`Tilemap`] and the colliders over its solid and sloped tiles.
+///
+/// Later tilemaps get drawn on top of earlier ones.
+#[derive(Debug)]
+struct LevelTilemap {
+  /// Where the top-left corner of the map goes.
+  corner: CoordVec,
+  tilemap: Tilemap,
+  colliders: TileColliders,
+}
+
+impl Level {
+  /// Load a level from a file, picking the format from the extension.
+  ///
+  /// Tile names and indices are checked against the given tilesets.
+  pub fn load(
+    path: &Path,
+    tilesets: &AHashMap<SmolStr, Tileset>,
+  ) -> eyre::Result<Level> {
+    let ext = path.extension().and_then(|ext| ext.to_str());
+    match ext {
+      Some("kdl") => {
+        let src = std::fs::read_to_string(path)?;
+        Level::load_kdl(&src, &path.display().to_string(), tilesets)
+      }
+      Some("tmj" | "tmx") => Level::load_tiled(path, tilesets),
+      _ => eyre::bail!("{} is not a known level format", path.display()),
+    }
+  }
+
+  /// Load a level from a KDL string.
+  ///
+  /// The tiles in `map` are placed in a grid starting at `origin`. Each
+  /// character is either looked up in the `legend` to find the blueprint to
+  /// instantiate, or in `tiles` to find the name of a tile in the `tileset`,
+  /// which says whether it's solid or a slope. Spaces are empty. `entities`
+  /// are placed at exact world positions instead, and can be given a `path`
+  /// of waypoints, as offsets from where they are. Entities `attached` to one
+  /// are placed relative to it too, and move along with it.
+  ///
+  /// The `filepath` argument is just for error reporting purposes.
+  pub fn load_kdl(
+    src: &str,
+    filepath: &str,
+    tilesets: &AHashMap<SmolStr, Tileset>,
+  ) -> eyre::Result<Level> {
+    #[derive(Deserialize)]
+    struct Raw {
+      origin: (i32, i32),
+      tile_size: i32,
+      player_spawn: (i32, i32),
+      tileset: SmolStr,
+      #[serde(default)]
+      legend: AHashMap<String, SmolStr>,
+      #[serde(default)]
+      tiles: AHashMap<String, SmolStr>,
+      map: String,
+      #[serde(default)]
+      entities: Vec<RawEntity>,
+    }
+    #[derive(Deserialize)]
+    struct RawEntity {
+      blueprint: SmolStr,
+      x: i32,
+      y: i32,
+      #[serde(default)]
+      path: Vec<(i32, i32)>,
+      /// Positioned relative to this one.
+      #[serde(default)]
+      attached: Vec<RawEntity>,
+    }
+    fn convert_entity(entity: RawEntity, origin: CoordVec) -> LevelSpawn {
+      let pos = origin + CoordVec::new(entity.x, entity.y);
+      LevelSpawn {
+        blueprint: entity.blueprint,
+        pos,
+        props: AHashMap::new(),
+        path: entity
+          .path
+          .into_iter()
+          .map(|(x, y)| pos + CoordVec::new(x, y))
+          .collect(),
+        attached: entity
+          .attached
+          .into_iter()
+          .map(|child| convert_entity(child, pos))
+          .collect(),
+      }
+    }
+
+    let doc: KdlDocument = src.parse()?;
+    // Knurdy deserializes nodes, not documents, so pretend the whole file
+    // is the children of one node
+    let mut node = KdlNode::new("level");
+    node.set_children(doc);
+    let raw: Raw = knurdy::deserialize_node(&node)
+      .map_err(|err| eyre::eyre!("in level {}: {}", filepath, err))?;
+
+    let in_level =
+      |err: eyre::Report| eyre::eyre!("in level {}: {}", filepath, err);
+    let legend = Tilemap::parse_legend(raw.legend).map_err(in_level)?;
+    let tile_legend = Tilemap::parse_legend(raw.tiles).map_err(in_level)?;
+    if let Some(ch) = legend.keys().find(|ch| tile_legend.contains_key(ch)) {
+      eyre::bail!(
+        "in level {}: {:?} is in both the legend and the tiles",
+        filepath,
+        ch
+      );
+    }
+
+    let tileset = tilesets.get(&raw.tileset).ok_or_else(|| {
+      eyre::eyre!("in level {}: no tileset named {:?}", filepath, raw.tileset)
+    })?;
+
+    let origin = CoordVec::new(raw.origin.0, raw.origin.1);
+    let mut spawns = Vec::new();
+    let tilemap = Tilemap::from_ascii(
+      &raw.map,
+      raw.tileset.clone(),
+      tileset,
+      raw.tile_size,
+      &tile_legend,
+      |ch, x, y| {
+        let Some(bp) = legend.get(&ch) else {
+          eyre::bail!("{:?} at ({}, {}) is not in the legend", ch, x, y);
+        };
+        spawns.push(LevelSpawn {
+          blueprint: bp.clone(),
+          pos: origin
+            + CoordVec::new(x as i32 * raw.tile_size, y as i32 * raw.tile_size),
+          props: AHashMap::new(),
+          path: Vec::new(),
+          attached: Vec::new(),
+        });
+        Ok(())
+      },
+    )
+    .map_err(in_level)?;
+    spawns.extend(
+      raw
+        .entities
+        .into_iter()
+        .map(|entity| convert_entity(entity, CoordVec::new(0, 0))),
+    );
+
+    Ok(Level {
+      player_spawn: CoordVec::new(raw.player_spawn.0, raw.player_spawn.1),
+      spawns,
+      tilemaps: vec![LevelTilemap::new(
+        origin - CoordVec::new(raw.tile_size / 2, raw.tile_size / 2),
+        tilemap,
+        tileset,
+        true,
+      )],
+    })
+  }
+
+  pub fn player_spawn(&self) -> CoordVec {
+    self.player_spawn
+  }
+
+  /// Spawn everything in the level into the world, then pick the tiles of
+  /// any autotile sprites now that their neighbors are there.
+  pub fn instantiate(
+    &self,
+    world: &mut World,
+    fab: &EntityFab,
+    ctx: &FabCtx,
+  ) -> Result<(), InstantiationError> {
+    for (idx, lt) in self.tilemaps.iter().enumerate() {
+      lt.instantiate(world, idx as u32);
+    }
+
+    for spawn in self.spawns.iter() {
+      spawn.instantiate(world, fab, ctx, None)?;
+    }
+
+    AutotileSprite::resolve_all(world, ctx);
+    Ok(())
+  }
+}
+
+impl LevelSpawn {
+  /// Spawn this and everything attached to it.
+  fn instantiate(
+    &self,
+    world: &mut World,
+    fab: &EntityFab,
+    ctx: &FabCtx,
+    parent: Option<(Entity, CoordVec)>,
+  ) -> Result<Entity, InstantiationError> {
+    let mut spawn_ctx = ctx.with_props(self.props.clone());
+    spawn_ctx.path = self.path.clone();
+    let mut builder = world.spawn().with(Positioned::new(self.pos));
+    if let Some((parent, parent_pos)) = parent {
+      // So it gets tracked as something that moves, unless the blueprint
+      // has its own
+      builder = builder.with(Mover::new()).with(AttachedTo {
+        parent,
+        offset: self.pos - parent_pos,
+      });
+    }
+    let e = fab.instantiate(&self.blueprint, builder, &spawn_ctx)?;
+
+    for child in self.attached.iter() {
+      child.instantiate(world, fab, ctx, Some((e, self.pos)))?;
+    }
+    Ok(e)
+  }
+}
+
+impl LevelTilemap {
+  /// This is where autotiles get resolved. If `collides` is false, the
+  /// tilemap is just decoration.
+  fn new(
+    corner: CoordVec,
+    mut tilemap: Tilemap,
+    tileset: &Tileset,
+    collides: bool,
+  ) -> Self {
+    tilemap.apply_autotiles(tileset);
+    let colliders = if collides {
+      TileColliders::new(&tilemap, tileset, corner)
+    } else {
+      TileColliders::default()
+    };
+    Self {
+      corner,
+      tilemap,
+      colliders,
+    }
+  }
+
+  fn instantiate(&self, world: &mut World, zlevel: u32) {
+    let dims = self.tilemap.dims();
+    let mut builder = world
+      .spawn()
+      .with(Positioned::new(
+        self.corner + CoordVec::new(dims.w / 2, dims.h / 2),
+      ))
+      .with(dims)
+      .with(ZLevel { level: zlevel })
+      .with(self.tilemap.clone());
+    self.colliders.spawn(&mut builder);
+    builder.build();
+  }
+}
@@ -0,0 +1,719 @@
+//! Importer for maps made in [Tiled](https://www.mapeditor.org/), in either
+//! the JSON (`.tmj`) or XML (`.tmx`) formats.
+//!
+//! - Tile layers become [`Tilemap`]s. Every tileset must be a single image
+//!   that's also the texture of a tileset in `resources/tilesets`, and each
+//!   layer can only use one tileset. Which tiles are solid or sloped comes
+//!   from that tileset. Set the bool property `collider` to `false` on a
+//!   layer to make it decoration only.
+//! - Objects in object layers instantiate the blueprint named by their type
+//!   (or class), with their custom properties fed into the
+//!   [`FabCtx`](crate::fabctx::FabCtx), like `speed` for things that follow a
+//!   path. The object with type `player-spawn` marks where the player
+//!   starts instead.
+//! - The map properties `origin-x` and `origin-y` are where the top-left
+//!   corner of the map goes in the world.
+
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use aglet::CoordVec;
+use ahash::AHashMap;
+use eyre::{bail, eyre, WrapErr};
+use kdl::KdlValue;
+use serde::Deserialize;
+use smol_str::SmolStr;
+
+use crate::{
+  ecm::component::Tilemap, resources::RESOURCES_ROOT, tileset::Tileset,
+};
+
+use super::{Level, LevelSpawn, LevelTilemap};
+
+const PLAYER_SPAWN_TYPE: &str = "player-spawn";
+
+/// Tiled stores flip flags in the high bits of the GID.
+const GID_MASK: u32 = 0x0fff_ffff;
+
+impl Level {
+  /// Load a level from a Tiled `.tmj` or `.tmx` file.
+  pub fn load_tiled(
+    path: &Path,
+    tilesets: &AHashMap<SmolStr, Tileset>,
+  ) -> eyre::Result<Level> {
+    let map = match path.extension().and_then(|ext| ext.to_str()) {
+      Some("tmx") => tmx::load_map(path),
+      _ => tmj::load_map(path),
+    }
+    .wrap_err_with(|| format!("in level {}", path.display()))?;
+    map
+      .into_level(tilesets)
+      .wrap_err_with(|| format!("in level {}", path.display()))
+  }
+}
+
+/// Format-agnostic version of the bits of a Tiled map we care about.
+struct Map {
+  tile_w: i32,
+  tile_h: i32,
+  props: Props,
+  tilesets: Vec<TilesetRef>,
+  layers: Vec<Layer>,
+}
+
+struct TilesetRef {
+  first_gid: u32,
+  /// The texture of the tileset's image.
+  texture: SmolStr,
+}
+
+enum Layer {
+  Tiles {
+    width: u32,
+    gids: Vec<u32>,
+    props: Props,
+  },
+  Objects(Vec<Object>),
+}
+
+struct Object {
+  kind: String,
+  x: f32,
+  y: f32,
+  w: f32,
+  h: f32,
+  /// Tile objects are anchored on their bottom-left instead of top-left.
+  is_tile: bool,
+  props: Props,
+}
+
+type Props = AHashMap<SmolStr, KdlValue>;
+
+impl Map {
+  fn into_level(
+    self,
+    tilesets: &AHashMap<SmolStr, Tileset>,
+  ) -> eyre::Result<Level> {
+    let origin = CoordVec::new(
+      prop_int(&self.props, "origin-x")?,
+      prop_int(&self.props, "origin-y")?,
+    );
+
+    let mut player_spawn = None;
+    let mut spawns = Vec::new();
+    let mut tilemaps = Vec::new();
+    for layer in self.layers.iter() {
+      match layer {
+        Layer::Tiles { width, gids, props } => {
+          let collides = match props.get("collider") {
+            None => true,
+            Some(KdlValue::Bool(it)) => *it,
+            Some(other) => bail!("layer property collider was {}", other),
+          };
+          if let Some(lt) =
+            self.convert_tile_layer(*width, gids, origin, collides, tilesets)?
+          {
+            tilemaps.push(lt);
+          }
+        }
+        Layer::Objects(objects) => {
+          for obj in objects {
+            if obj.kind.is_empty() {
+              continue;
+            }
+            let top = if obj.is_tile { obj.y - obj.h } else { obj.y };
+            let pos = origin
+              + CoordVec::new(
+                (obj.x + obj.w / 2.0).round() as i32,
+                (top + obj.h / 2.0).round() as i32,
+              );
+            if obj.kind == PLAYER_SPAWN_TYPE {
+              if player_spawn.replace(pos).is_some() {
+                bail!("more than one {} object", PLAYER_SPAWN_TYPE);
+              }
+            } else {
+              spawns.push(LevelSpawn {
+                blueprint: obj.kind.as_str().into(),
+                pos,
+                props: obj.props.clone(),
+                path: Vec::new(),
+                attached: Vec::new(),
+              });
+            }
+          }
+        }
+      }
+    }
+
+    let player_spawn = player_spawn
+      .ok_or_else(|| eyre!("no object with type {}", PLAYER_SPAWN_TYPE))?;
+    Ok(Level {
+      player_spawn,
+      spawns,
+      tilemaps,
+    })
+  }
+
+  /// Returns `None` for empty layers.
+  fn convert_tile_layer(
+    &self,
+    width: u32,
+    gids: &[u32],
+    corner: CoordVec,
+    collides: bool,
+    tilesets: &AHashMap<SmolStr, Tileset>,
+  ) -> eyre::Result<Option<LevelTilemap>> {
+    let gids = gids.iter().map(|gid| gid & GID_MASK).collect::<Vec<_>>();
+    let Some(first) = gids.iter().copied().find(|gid| *gid != 0) else {
+      return Ok(None);
+    };
+    let ts_ref = self.lookup_gid(first)?;
+    let (name, tileset) = tilesets
+      .iter()
+      .find(|(_, ts)| ts.texture() == ts_ref.texture)
+      .ok_or_else(|| {
+        eyre!("no tileset in resources uses the image {}", ts_ref.texture)
+      })?;
+
+    let height = (gids.len() as u32).div_ceil(width);
+    let mut tilemap =
+      Tilemap::new(name.clone(), self.tile_w, self.tile_h, width, height);
+    for (idx, gid) in gids.iter().copied().enumerate() {
+      if gid == 0 {
+        continue;
+      }
+      if self.lookup_gid(gid)?.first_gid != ts_ref.first_gid {
+        bail!("tile layers can only use one tileset each");
+      }
+      let local = gid - ts_ref.first_gid;
+      if local as usize >= tileset.len() {
+        bail!("tileset {:?} only has {} tiles", name, tileset.len());
+      }
+      tilemap.set(idx as u32 % width, idx as u32 / width, Some(local as u16));
+    }
+
+    Ok(Some(LevelTilemap::new(corner, tilemap, tileset, collides)))
+  }
+
+  fn lookup_gid(&self, gid: u32) -> eyre::Result<&TilesetRef> {
+    self
+      .tilesets
+      .iter()
+      .filter(|ts| ts.first_gid <= gid)
+      .max_by_key(|ts| ts.first_gid)
+      .ok_or_else(|| eyre!("no tileset has tile {}", gid))
+  }
+}
+
+fn prop_int(props: &Props, key: &str) -> eyre::Result<i32> {
+  match props.get(key) {
+    None => Ok(0),
+    Some(KdlValue::Base10(it)) => Ok(*it as i32),
+    Some(other) => {
+      bail!("property {} should be an int, but was {}", key, other)
+    }
+  }
+}
+
+/// Turn a path to an image on disk into the name of the texture in
+/// [`Resources`](crate::resources::Resources).
+fn texture_name(image: &Path) -> eyre::Result<SmolStr> {
+  let tex_root = [RESOURCES_ROOT, "textures"]
+    .into_iter()
+    .collect::<PathBuf>()
+    .canonicalize()?;
+  let image = image
+    .canonicalize()
+    .wrap_err_with(|| format!("couldn't find image {}", image.display()))?;
+  let rel = image.strip_prefix(&tex_root).map_err(|_| {
+    eyre!(
+      "image {} is not under {}",
+      image.display(),
+      tex_root.display()
+    )
+  })?;
+  Ok(
+    rel
+      .with_extension("")
+      .to_string_lossy()
+      .replace('\\', "/")
+      .into(),
+  )
+}
+
+/// Tiled writes properties as `{name, type, value}` triples.
+fn convert_prop(ty: &str, value: &str) -> eyre::Result<KdlValue> {
+  Ok(match ty {
+    "int" | "object" => KdlValue::Base10(value.parse()?),
+    "float" => KdlValue::Base10Float(value.parse()?),
+    "bool" => KdlValue::Bool(value.parse()?),
+    _ => KdlValue::String(value.to_owned()),
+  })
+}
+
+mod tmj {
+  use serde_json::Value;
+
+  use super::*;
+
+  #[derive(Deserialize)]
+  struct RawMap {
+    tilewidth: i32,
+    tileheight: i32,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    properties: Vec<RawProp>,
+    #[serde(default)]
+    tilesets: Vec<RawTilesetRef>,
+    #[serde(default)]
+    layers: Vec<RawLayer>,
+  }
+
+  #[derive(Deserialize)]
+  struct RawProp {
+    name: SmolStr,
+    #[serde(rename = "type", default)]
+    ty: String,
+    value: Value,
+  }
+
+  #[derive(Deserialize)]
+  struct RawTilesetRef {
+    firstgid: u32,
+    source: Option<String>,
+    #[serde(flatten)]
+    embedded: RawTileset,
+  }
+
+  #[derive(Deserialize)]
+  struct RawTileset {
+    image: Option<String>,
+  }
+
+  #[derive(Deserialize)]
+  #[serde(tag = "type", rename_all = "lowercase")]
+  enum RawLayer {
+    TileLayer {
+      width: u32,
+      data: Value,
+      #[serde(default)]
+      properties: Vec<RawProp>,
+    },
+    ObjectGroup {
+      objects: Vec<RawObject>,
+    },
+    Group {
+      #[serde(default)]
+      layers: Vec<RawLayer>,
+    },
+    ImageLayer {},
+  }
+
+  #[derive(Deserialize)]
+  struct RawObject {
+    #[serde(rename = "type", alias = "class", default)]
+    kind: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    gid: Option<u32>,
+    #[serde(default)]
+    properties: Vec<RawProp>,
+  }
+
+  pub(super) fn load_map(path: &Path) -> eyre::Result<Map> {
+    let raw: RawMap = serde_json::from_slice(&fs::read(path)?)?;
+    if raw.infinite {
+      bail!("infinite maps aren't supported");
+    }
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut tilesets = Vec::new();
+    for ts in raw.tilesets {
+      let tileset = match ts.source {
+        Some(source) => {
+          let ts_path = dir.join(source);
+          let raw: RawTileset = serde_json::from_slice(&fs::read(&ts_path)?)
+            .wrap_err_with(|| format!("in tileset {}", ts_path.display()))?;
+          convert_tileset(ts.firstgid, raw, ts_path.parent().unwrap())?
+        }
+        None => convert_tileset(ts.firstgid, ts.embedded, dir)?,
+      };
+      tilesets.push(tileset);
+    }
+
+    let mut layers = Vec::new();
+    flatten_layers(raw.layers, &mut layers)?;
+
+    Ok(Map {
+      tile_w: raw.tilewidth,
+      tile_h: raw.tileheight,
+      props: convert_props(raw.properties)?,
+      tilesets,
+      layers,
+    })
+  }
+
+  fn convert_tileset(
+    first_gid: u32,
+    raw: RawTileset,
+    dir: &Path,
+  ) -> eyre::Result<TilesetRef> {
+    let Some(image) = raw.image else {
+      bail!("only single-image tilesets are supported");
+    };
+    Ok(TilesetRef {
+      first_gid,
+      texture: texture_name(&dir.join(image))?,
+    })
+  }
+
+  fn flatten_layers(
+    raw: Vec<RawLayer>,
+    out: &mut Vec<Layer>,
+  ) -> eyre::Result<()> {
+    for layer in raw {
+      match layer {
+        RawLayer::TileLayer {
+          width,
+          data,
+          properties,
+        } => {
+          let gids = serde_json::from_value(data)
+            .map_err(|_| eyre!("tile layer data must be CSV, not base64"))?;
+          out.push(Layer::Tiles {
+            width,
+            gids,
+            props: convert_props(properties)?,
+          });
+        }
+        RawLayer::ObjectGroup { objects } => {
+          let objects = objects
+            .into_iter()
+            .map(|obj| {
+              Ok(Object {
+                kind: obj.kind,
+                x: obj.x,
+                y: obj.y,
+                w: obj.width,
+                h: obj.height,
+                is_tile: obj.gid.is_some(),
+                props: convert_props(obj.properties)?,
+              })
+            })
+            .collect::<eyre::Result<_>>()?;
+          out.push(Layer::Objects(objects));
+        }
+        RawLayer::Group { layers } => flatten_layers(layers, out)?,
+        RawLayer::ImageLayer {} => {}
+      }
+    }
+    Ok(())
+  }
+
+  fn convert_props(raw: Vec<RawProp>) -> eyre::Result<Props> {
+    let mut out = AHashMap::new();
+    for prop in raw {
+      let value = match prop.value {
+        Value::Bool(it) => KdlValue::Bool(it),
+        Value::String(it) => convert_prop(&prop.ty, &it)?,
+        Value::Number(it) => match it.as_i64() {
+          Some(int) => KdlValue::Base10(int),
+          None => KdlValue::Base10Float(it.as_f64().unwrap_or_default()),
+        },
+        _ => bail!("property {} has an unsupported type", prop.name),
+      };
+      out.insert(prop.name, value);
+    }
+    Ok(out)
+  }
+}
+
+mod tmx {
+  use super::*;
+
+  #[derive(Deserialize)]
+  struct RawMap {
+    #[serde(rename = "@tilewidth")]
+    tilewidth: i32,
+    #[serde(rename = "@tileheight")]
+    tileheight: i32,
+    #[serde(rename = "@infinite", default)]
+    infinite: u8,
+    #[serde(default)]
+    properties: RawProps,
+    #[serde(rename = "tileset", default)]
+    tilesets: Vec<RawTilesetRef>,
+    #[serde(rename = "layer", default)]
+    tiles: Vec<RawTileLayer>,
+    #[serde(rename = "objectgroup", default)]
+    objects: Vec<RawObjectGroup>,
+    #[serde(rename = "group", default)]
+    groups: Vec<RawLayers>,
+  }
+
+  /// Quick-xml doesn't like `#[serde(flatten)]`, so this is duplicated with
+  /// the fields on [`RawMap`].
+  #[derive(Deserialize)]
+  struct RawLayers {
+    #[serde(rename = "layer", default)]
+    tiles: Vec<RawTileLayer>,
+    #[serde(rename = "objectgroup", default)]
+    objects: Vec<RawObjectGroup>,
+    #[serde(rename = "group", default)]
+    groups: Vec<RawLayers>,
+  }
+
+  #[derive(Deserialize, Default)]
+  struct RawProps {
+    #[serde(default)]
+    property: Vec<RawProp>,
+  }
+
+  #[derive(Deserialize)]
+  struct RawProp {
+    #[serde(rename = "@name")]
+    name: SmolStr,
+    #[serde(rename = "@type", default)]
+    ty: String,
+    #[serde(rename = "@value", default)]
+    value: String,
+  }
+
+  #[derive(Deserialize)]
+  struct RawTilesetRef {
+    #[serde(rename = "@firstgid")]
+    firstgid: u32,
+    #[serde(rename = "@source")]
+    source: Option<String>,
+    image: Option<RawImage>,
+  }
+
+  #[derive(Deserialize)]
+  struct RawTileset {
+    image: Option<RawImage>,
+  }
+
+  #[derive(Deserialize)]
+  struct RawImage {
+    #[serde(rename = "@source")]
+    source: String,
+  }
+
+  #[derive(Deserialize)]
+  struct RawTileLayer {
+    #[serde(rename = "@width")]
+    width: u32,
+    #[serde(default)]
+    properties: RawProps,
+    data: RawData,
+  }
+
+  #[derive(Deserialize)]
+  struct RawData {
+    #[serde(rename = "@encoding", default)]
+    encoding: String,
+    #[serde(rename = "$text", default)]
+    text: String,
+  }
+
+  #[derive(Deserialize)]
+  struct RawObjectGroup {
+    #[serde(rename = "object", default)]
+    objects: Vec<RawObject>,
+  }
+
+  #[derive(Deserialize)]
+  struct RawObject {
+    #[serde(rename = "@type", alias = "@class", default)]
+    kind: String,
+    #[serde(rename = "@x")]
+    x: f32,
+    #[serde(rename = "@y")]
+    y: f32,
+    #[serde(rename = "@width", default)]
+    width: f32,
+    #[serde(rename = "@height", default)]
+    height: f32,
+    #[serde(rename = "@gid")]
+    gid: Option<u32>,
+    #[serde(default)]
+    properties: RawProps,
+  }
+
+  pub(super) fn load_map(path: &Path) -> eyre::Result<Map> {
+    let raw: RawMap = quick_xml::de::from_str(&fs::read_to_string(path)?)?;
+    if raw.infinite != 0 {
+      bail!("infinite maps aren't supported");
+    }
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut tilesets = Vec::new();
+    for ts in raw.tilesets {
+      let tileset = match ts.source {
+        Some(source) => {
+          let ts_path = dir.join(source);
+          let raw: RawTileset =
+            quick_xml::de::from_str(&fs::read_to_string(&ts_path)?)
+              .wrap_err_with(|| format!("in tileset {}", ts_path.display()))?;
+          convert_tileset(ts.firstgid, raw, ts_path.parent().unwrap())?
+        }
+        None => {
+          convert_tileset(ts.firstgid, RawTileset { image: ts.image }, dir)?
+        }
+      };
+      tilesets.push(tileset);
+    }
+
+    let mut layers = Vec::new();
+    flatten_layers(
+      RawLayers {
+        tiles: raw.tiles,
+        objects: raw.objects,
+        groups: raw.groups,
+      },
+      &mut layers,
+    )?;
+
+    Ok(Map {
+      tile_w: raw.tilewidth,
+      tile_h: raw.tileheight,
+      props: convert_props(raw.properties)?,
+      tilesets,
+      layers,
+    })
+  }
+
+  fn convert_tileset(
+    first_gid: u32,
+    raw: RawTileset,
+    dir: &Path,
+  ) -> eyre::Result<TilesetRef> {
+    let Some(image) = raw.image else {
+      bail!("only single-image tilesets are supported");
+    };
+    Ok(TilesetRef {
+      first_gid,
+      texture: texture_name(&dir.join(image.source))?,
+    })
+  }
+
+  /// The XML format keeps different kinds of layers in different lists, so
+  /// this doesn't preserve the order between tile and object layers.
+  /// Thankfully nothing cares.
+  fn flatten_layers(raw: RawLayers, out: &mut Vec<Layer>) -> eyre::Result<()> {
+    for layer in raw.tiles {
+      if layer.data.encoding != "csv" {
+        bail!("tile layer data must be CSV, not {:?}", layer.data.encoding);
+      }
+      let gids = layer
+        .data
+        .text
+        .split(',')
+        .map(|gid| gid.trim().parse())
+        .collect::<Result<_, _>>()?;
+      out.push(Layer::Tiles {
+        width: layer.width,
+        gids,
+        props: convert_props(layer.properties)?,
+      });
+    }
+    for group in raw.objects {
+      let objects = group
+        .objects
+        .into_iter()
+        .map(|obj| {
+          Ok(Object {
+            kind: obj.kind,
+            x: obj.x,
+            y: obj.y,
+            w: obj.width,
+            h: obj.height,
+            is_tile: obj.gid.is_some(),
+            props: convert_props(obj.properties)?,
+          })
+        })
+        .collect::<eyre::Result<_>>()?;
+      out.push(Layer::Objects(objects));
+    }
+    for group in raw.groups {
+      flatten_layers(group, out)?;
+    }
+    Ok(())
+  }
+
+  fn convert_props(raw: RawProps) -> eyre::Result<Props> {
+    let mut out = AHashMap::new();
+    for prop in raw.property {
+      let value = convert_prop(&prop.ty, &prop.value)?;
+      out.insert(prop.name, value);
+    }
+    Ok(out)
+  }
+}
+
+#[cfg(test)]
+fn load_fixtures() -> Vec<Level> {
+  let tileset_path = [RESOURCES_ROOT, "tilesets", "main.kdl"]
+    .into_iter()
+    .collect::<PathBuf>();
+  let tileset =
+    Tileset::load_kdl(&fs::read_to_string(&tileset_path).unwrap(), "main.kdl")
+      .unwrap();
+  let tilesets = [(SmolStr::from("main"), tileset)].into_iter().collect();
+
+  ["tiled-json.tmj", "tiled-xml.tmx"]
+    .into_iter()
+    .map(|name| {
+      let path = [RESOURCES_ROOT, "levels", "test", name]
+        .into_iter()
+        .collect::<PathBuf>();
+      Level::load_tiled(&path, &tilesets).unwrap()
+    })
+    .collect()
+}
+
+#[test]
+fn tiled_flip_flags() {
+  for level in load_fixtures() {
+    let ground = &level.tilemaps[0];
+    assert_eq!(ground.corner, CoordVec::new(-32, -16));
+    // Flipped rust floor, slope up and slope down
+    let row = (0..4).map(|x| ground.tilemap.get(x, 2)).collect::<Vec<_>>();
+    assert_eq!(row, vec![Some(16), Some(16), Some(17), Some(18)]);
+    assert_eq!(ground.colliders.slopes.len(), 2);
+
+    // The decor layer has a slope too, but no colliders
+    let decor = &level.tilemaps[1];
+    assert_eq!(decor.tilemap.get(0, 0), Some(17));
+    assert!(decor.colliders.solid.is_empty());
+    assert!(decor.colliders.slopes.is_empty());
+  }
+}
+
+#[test]
+fn tiled_objects() {
+  for level in load_fixtures() {
+    assert_eq!(level.player_spawn, CoordVec::new(-24, -8));
+
+    let [platform] = level.spawns.as_slice() else {
+      panic!("expected one spawn, got {:?}", level.spawns);
+    };
+    assert_eq!(platform.blueprint, "moving-platform");
+    assert_eq!(platform.pos, CoordVec::new(0, -9));
+    assert_eq!(
+      platform.props.get("label"),
+      Some(&KdlValue::String("hello".to_owned()))
+    );
+    assert_eq!(
+      platform.props.get("speed"),
+      Some(&KdlValue::Base10Float(45.5))
+    );
+  }
+}
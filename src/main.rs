@@ -1,8 +1,14 @@
 use controls::GilrsState;
 use dialga::EntityFabricator;
+use ecm::{
+  component::{PlayerController, Positioned, Velocitized},
+  snapshot::Snapshot,
+};
 use fabctx::FabCtx;
 use gfx::{width_height_deficit, GAME_HEIGHT, GAME_WIDTH};
 use macroquad::prelude::*;
+use palkia::prelude::AccessQuery;
+use replay::{InputSource, Recording};
 use resources::Resources;
 use states::StateGameplay;
 
@@ -11,8 +17,23 @@ mod ecm;
 mod fabctx;
 mod geom;
 mod gfx;
+mod level;
+mod replay;
 mod resources;
+mod sprite;
 mod states;
+mod tileset;
+
+const START_LEVEL: &str = "start";
+/// How many times the game updates per second.
+const TICKS_PER_SECOND: u32 = 60;
+/// The most ticks to run in one frame. If the game falls further behind than
+/// this, it slows down instead of spending ages catching up.
+const MAX_TICKS_PER_FRAME: u32 = 5;
+/// Where F9 saves recordings of the current run to.
+const RECORDINGS_DIR: &str = "./recordings";
+/// Where F6 quicksaves to and F7 quickloads from.
+const QUICKSAVE_PATH: &str = "./saves/quick.snapshot";
 
 fn conf() -> Conf {
   Conf {
@@ -23,18 +44,116 @@ fn conf() -> Conf {
   }
 }
 
-#[macroquad::main(conf)]
-async fn main() {
+const USAGE: &str = "\
+usage: gwair [--replay RECORDING [--headless]]
+
+  --replay RECORDING  play back a recording saved with F9
+  --headless          run the replay without a window, and print where the
+                      player ended up";
+
+/// Command-line options.
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+  /// `--replay some-recording.json` plays it back instead of taking controls.
+  replay: Option<String>,
+  /// `--headless` runs the replay without a window, and prints where the
+  /// player ended up.
+  headless: bool,
+}
+
+impl Args {
+  /// Parse the arguments, not including the program name.
+  fn parse(args: impl IntoIterator<Item = String>) -> eyre::Result<Args> {
+    let mut out = Args::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+      match arg.as_str() {
+        "--replay" => {
+          let path = args
+            .next()
+            .ok_or_else(|| eyre::eyre!("--replay needs a path"))?;
+          out.replay = Some(path);
+        }
+        "--headless" => out.headless = true,
+        other => eyre::bail!("unknown argument {:?}", other),
+      }
+    }
+    if out.headless && out.replay.is_none() {
+      eyre::bail!("--headless needs a --replay to run");
+    }
+    Ok(out)
+  }
+}
+
+fn main() {
+  let args = Args::parse(std::env::args().skip(1))
+    .unwrap_or_else(|err| exit_with_usage(err));
+  let recording = args
+    .replay
+    .as_ref()
+    .map(Recording::load)
+    .transpose()
+    .unwrap_or_else(|err| exit_with_usage(err));
+  if args.headless {
+    run_headless(recording.expect("checked when parsing"));
+  } else {
+    macroquad::Window::from_config(conf(), run_windowed(recording));
+  }
+}
+
+/// Say what was wrong with how it was run, and how to run it, then quit.
+fn exit_with_usage(err: eyre::Report) -> ! {
+  eprintln!("{:#}\n\n{}", err, USAGE);
+  std::process::exit(2);
+}
+
+fn run_headless(recording: Recording) {
+  let tick_count = recording.ticks.len();
+
+  let resources = Resources::load_headless().unwrap();
+  let mut state = StateGameplay::from_resources(
+    &resources,
+    &recording.level.clone(),
+    InputSource::replay(recording),
+  )
+  .unwrap_or_else(|err| exit_with_usage(err));
+  for _ in 0..tick_count {
+    state.on_update().unwrap();
+  }
+
+  let world = state.world();
+  let player = state.player();
+  let pos = world.query::<&Positioned>(player).unwrap();
+  let vel = world.query::<&Velocitized>(player).unwrap();
+  let controller = world.query::<&PlayerController>(player).unwrap();
+  println!("after {} ticks:", tick_count);
+  println!("  position {:?}", pos.pos);
+  println!("  velocity {:?}", vel.vel);
+  println!("  state {:?}", controller.state());
+}
+
+async fn run_windowed(recording: Option<Recording>) {
   let resources = Resources::load().unwrap();
   Resources::swap(resources);
 
   GilrsState::init();
 
+  let state = match recording {
+    Some(recording) => StateGameplay::new(
+      &recording.level.clone(),
+      InputSource::replay(recording),
+    )
+    .unwrap_or_else(|err| exit_with_usage(err)),
+    None => StateGameplay::new(START_LEVEL, InputSource::Live).unwrap(),
+  };
+
   let canvas = render_target(GAME_WIDTH as u32, GAME_HEIGHT as u32);
   canvas.texture.set_filter(FilterMode::Nearest);
   let mut app = App {
     canvas,
-    state: StateGameplay::new(),
+    state,
+    error: None,
+    accumulator: 0.0,
   };
 
   loop {
@@ -49,10 +168,77 @@ struct App {
   canvas: RenderTarget,
 
   state: StateGameplay,
+  /// What went wrong last, with a heading saying what was being done, to
+  /// show on screen until the next hot-reload.
+  error: Option<String>,
+  /// Time that's passed that hasn't been ticked for yet, in seconds.
+  accumulator: f32,
 }
 impl App {
   fn update(&mut self) {
-    self.state.on_update();
+    if is_key_pressed(KeyCode::F5) {
+      self.error = self
+        .reload()
+        .err()
+        .map(|err| format!("Couldn't reload (F5 to try again):\n{:?}", err));
+    }
+    if is_key_pressed(KeyCode::F6) {
+      match self.quicksave() {
+        Ok(()) => println!("quicksaved to {}", QUICKSAVE_PATH),
+        Err(err) => eprintln!("couldn't quicksave: {:?}", err),
+      }
+    }
+    if is_key_pressed(KeyCode::F7) {
+      if let Err(err) = self.quickload() {
+        eprintln!("couldn't quickload: {:?}", err);
+      }
+    }
+    if is_key_pressed(KeyCode::F9) {
+      match self.state.recording().save(RECORDINGS_DIR) {
+        Ok(path) => println!("saved recording to {}", path.display()),
+        Err(err) => eprintln!("couldn't save recording: {:?}", err),
+      }
+    }
+
+    let ticks;
+    (ticks, self.accumulator) =
+      advance_clock(self.accumulator, get_frame_time());
+    for _ in 0..ticks {
+      if let Err(err) = self.state.on_update() {
+        self.error = Some(format!("Error (F5 to reload):\n{:?}", err));
+      }
+    }
+
+    self.state.on_ui();
+  }
+
+  fn quicksave(&mut self) -> eyre::Result<()> {
+    self.state.snapshot()?.save(QUICKSAVE_PATH)
+  }
+
+  fn quickload(&mut self) -> eyre::Result<()> {
+    let snapshot = Snapshot::load(QUICKSAVE_PATH)?;
+    self.state.restore(&snapshot)
+  }
+
+  /// Load all the resources again and restart the level with them.
+  ///
+  /// If anything goes wrong, the game carries on with the old ones.
+  fn reload(&mut self) -> eyre::Result<()> {
+    let resources = Resources::load()?;
+    let old = Resources::swap(resources);
+    match StateGameplay::new(self.state.level_name(), InputSource::Live) {
+      Ok(state) => {
+        self.state = state;
+        Ok(())
+      }
+      Err(err) => {
+        if let Some(old) = old {
+          Resources::swap(old);
+        }
+        Err(err)
+      }
+    }
   }
 
   fn draw(&self) {
@@ -69,7 +255,7 @@ impl App {
     });
 
     clear_background(WHITE);
-    self.state.on_draw();
+    self.state.on_draw(interpolation_alpha(self.accumulator));
 
     // Done rendering to the canvas; go back to our normal camera
     // to size the canvas
@@ -93,7 +279,97 @@ impl App {
         ..Default::default()
       },
     );
+
+    if let Some(err) = &self.error {
+      let font_size = 20.0;
+      draw_rectangle(
+        0.0,
+        0.0,
+        screen_width(),
+        font_size * (err.lines().count() as f32 + 0.5),
+        Color::new(0.0, 0.0, 0.0, 0.8),
+      );
+      for (idx, line) in err.lines().enumerate() {
+        draw_text(line, 8.0, font_size * (idx as f32 + 1.0), font_size, RED);
+      }
+    }
   }
 }
 
+/// Add a frame's worth of time, in seconds, to the time that hasn't been ticked
+/// for yet. Returns how many ticks to run, and the time left over after them.
+fn advance_clock(accumulator: f32, frame_time: f32) -> (u32, f32) {
+  let dt = (TICKS_PER_SECOND as f32).recip();
+  let mut accumulator = accumulator + frame_time;
+  let mut ticks = 0;
+  while accumulator >= dt && ticks < MAX_TICKS_PER_FRAME {
+    accumulator -= dt;
+    ticks += 1;
+  }
+  if ticks == MAX_TICKS_PER_FRAME {
+    accumulator %= dt;
+  }
+  (ticks, accumulator)
+}
+
+/// How far it is from the last tick to the next one, from 0 to 1, given the
+/// time left over from [`advance_clock`].
+fn interpolation_alpha(accumulator: f32) -> f32 {
+  let dt = (TICKS_PER_SECOND as f32).recip();
+  (accumulator / dt).clamp(0.0, 1.0)
+}
+
 type EntityFab = EntityFabricator<FabCtx>;
+
+#[test]
+fn clock_ticks_at_fixed_rate() {
+  // Ten seconds at 144fps
+  let mut accumulator = 0.0;
+  let mut total = 0;
+  for _ in 0..1440 {
+    let ticks;
+    (ticks, accumulator) = advance_clock(accumulator, 1.0 / 144.0);
+    assert!(ticks <= 1);
+    total += ticks;
+
+    let alpha = interpolation_alpha(accumulator);
+    assert!((0.0..=1.0).contains(&alpha));
+  }
+  assert!(
+    total.abs_diff(10 * TICKS_PER_SECOND) <= 1,
+    "ran {} ticks",
+    total
+  );
+}
+
+#[test]
+fn clock_clamps_long_frames() {
+  // A whole second of lag only runs a few ticks, and doesn't carry the rest
+  // over to the next frame
+  let (ticks, accumulator) = advance_clock(0.0, 1.0);
+  assert_eq!(ticks, MAX_TICKS_PER_FRAME);
+  assert!(accumulator < (TICKS_PER_SECOND as f32).recip());
+
+  let (ticks, _) = advance_clock(accumulator, 0.0);
+  assert_eq!(ticks, 0);
+  let alpha = interpolation_alpha(accumulator);
+  assert!((0.0..1.0).contains(&alpha));
+}
+
+#[test]
+fn parse_args() {
+  let parse =
+    |args: &[&str]| Args::parse(args.iter().map(|arg| arg.to_string()));
+
+  assert_eq!(parse(&[]).unwrap(), Args::default());
+  assert_eq!(
+    parse(&["--replay", "run.json", "--headless"]).unwrap(),
+    Args {
+      replay: Some("run.json".to_owned()),
+      headless: true,
+    }
+  );
+  assert!(parse(&["--replay"]).is_err());
+  assert!(parse(&["--headless"]).is_err());
+  assert!(parse(&["--bogus"]).is_err());
+}
@@ -0,0 +1,150 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use eyre::WrapErr;
+use serde::{Deserialize, Serialize};
+
+use crate::controls::ControlState;
+
+/// Where each tick's controls come from.
+#[derive(Debug)]
+pub enum InputSource {
+  /// The keyboard and gamepad.
+  Live,
+  /// Controls recorded earlier, played back one per tick. Once they run out,
+  /// nothing is pressed.
+  Replay {
+    ticks: Vec<ControlState>,
+    next: usize,
+  },
+}
+
+impl InputSource {
+  pub fn replay(recording: Recording) -> Self {
+    Self::scripted(recording.ticks)
+  }
+
+  /// Play back the given controls, one per tick.
+  pub fn scripted(ticks: Vec<ControlState>) -> Self {
+    InputSource::Replay { ticks, next: 0 }
+  }
+
+  /// Get the controls for this tick.
+  pub fn sample(&mut self) -> ControlState {
+    match self {
+      InputSource::Live => ControlState::calculate(),
+      InputSource::Replay { ticks, next } => {
+        let controls = ticks.get(*next).copied().unwrap_or_default();
+        *next += 1;
+        controls
+      }
+    }
+  }
+}
+
+/// Every tick's controls from the start of a level, which play back the same
+/// run exactly.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+  pub level: String,
+  pub ticks: Vec<ControlState>,
+}
+
+impl Recording {
+  pub fn new(level: &str) -> Self {
+    Self {
+      level: level.to_owned(),
+      ticks: Vec::new(),
+    }
+  }
+
+  pub fn load(path: impl AsRef<Path>) -> eyre::Result<Recording> {
+    let path = path.as_ref();
+    let file = fs::read_to_string(path).wrap_err_with(|| {
+      format!("couldn't read recording {}", path.display())
+    })?;
+    serde_json::from_str(&file)
+      .map_err(|err| eyre::eyre!("in recording {}: {}", path.display(), err))
+  }
+
+  /// Save to a new file in the given directory, returning where it went.
+  pub fn save(&self, dir: impl AsRef<Path>) -> eyre::Result<PathBuf> {
+    let stamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let path = dir.as_ref().join(format!(
+      "{}-{}.json",
+      self.level.replace('/', "-"),
+      stamp
+    ));
+    fs::create_dir_all(dir)?;
+    fs::write(&path, serde_json::to_string(self)?)?;
+    Ok(path)
+  }
+}
+
+#[test]
+fn recording_roundtrip() {
+  use crate::{
+    ecm::component::{PathFollower, PlayerController, Positioned, Velocitized},
+    resources::Resources,
+    states::{StateGameplay, SANDBOX_LEVEL},
+  };
+  use itertools::Itertools;
+  use palkia::prelude::*;
+
+  // Everything that moves, entity by entity. Snapshots can't be compared
+  // directly, because entities come out in hash order.
+  let moving_parts = |state: &StateGameplay| {
+    let world = state.world();
+    world
+      .entities()
+      .map(|e| {
+        format!(
+          "{:?} {:?} {:?} {:?} {:?}",
+          e,
+          world.query::<&Positioned>(e).map(|pos| pos.pos),
+          world.query::<&Velocitized>(e).map(|vel| vel.vel),
+          world
+            .query::<&PlayerController>(e)
+            .map(|player| format!("{:?}", player.state())),
+          world
+            .query::<&PathFollower>(e)
+            .map(|path| format!("{:?}", *path)),
+        )
+      })
+      .sorted()
+      .collect::<Vec<_>>()
+  };
+
+  let walk = ControlState {
+    movement: glam::Vec2::X,
+    ..Default::default()
+  };
+  let jump = ControlState { jump: true, ..walk };
+  let mut ticks = vec![walk; 40];
+  ticks.extend(vec![jump; 15]);
+  ticks.extend(vec![ControlState::default(); 95]);
+  let mut recorded = StateGameplay::sandbox(ticks);
+  recorded.run(150);
+
+  let dir = std::env::temp_dir()
+    .join(format!("gwair-recording-roundtrip-{}", std::process::id()));
+  let path = recorded.recording().save(&dir).unwrap();
+  let recording = Recording::load(&path).unwrap();
+  fs::remove_dir_all(&dir).unwrap();
+  assert_eq!(recording.level, SANDBOX_LEVEL);
+  assert_eq!(recording.ticks.len(), 150);
+
+  // Same as `--replay` does
+  let resources = Resources::load_headless().unwrap();
+  let mut replayed = StateGameplay::from_resources(
+    &resources,
+    &recording.level.clone(),
+    InputSource::replay(recording),
+  )
+  .unwrap();
+  replayed.run(150);
+  assert_eq!(moving_parts(&replayed), moving_parts(&recorded));
+}
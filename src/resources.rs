@@ -2,19 +2,25 @@ use std::{
   fs, io,
   ops::Deref,
   path::{Path, PathBuf},
-  sync::{Mutex, MutexGuard},
+  sync::{Arc, Mutex, MutexGuard},
 };
 
 use ahash::AHashMap;
 use macroquad::prelude as mq;
 use smol_str::SmolStr;
 
-use crate::{ecm, EntityFab};
+use crate::{
+  ecm, level::Level, sprite::SpriteSheet, tileset::Tileset, EntityFab,
+};
 
 pub struct Resources {
   textures: AHashMap<SmolStr, mq::Texture2D>,
-  fallback_tex: mq::Texture2D,
-  fabber: EntityFab,
+  /// `None` when loaded headless.
+  fallback_tex: Option<mq::Texture2D>,
+  sprite_sheets: Arc<AHashMap<SmolStr, SpriteSheet>>,
+  fabber: Arc<EntityFab>,
+  tilesets: Arc<AHashMap<SmolStr, Tileset>>,
+  levels: AHashMap<SmolStr, Level>,
 }
 
 #[cfg(debug_assertions)]
@@ -25,11 +31,27 @@ pub const RESOURCES_ROOT: &str = "./resources";
 
 impl Resources {
   pub fn load() -> eyre::Result<Resources> {
+    Self::load_inner(true)
+  }
+
+  /// Load everything except the textures, which need a window to exist.
+  ///
+  /// This is enough to run the game without drawing it, like in tests.
+  pub fn load_headless() -> eyre::Result<Resources> {
+    Self::load_inner(false)
+  }
+
+  fn load_inner(with_textures: bool) -> eyre::Result<Resources> {
     let tex_root = [RESOURCES_ROOT, "textures"]
       .into_iter()
       .collect::<PathBuf>();
     let mut textures = AHashMap::new();
-    for path in all_subpaths(&tex_root, "png")? {
+    let png_paths = if with_textures {
+      all_subpaths(&tex_root, "png")?
+    } else {
+      Vec::new()
+    };
+    for path in png_paths {
       let abs_path = tex_root.join(&path);
       let file = fs::read(&abs_path)?;
       // MQ just panics here, but like ... yeah fine
@@ -43,14 +65,31 @@ impl Resources {
         .into();
       textures.insert(stem, tex);
     }
-    let fallback_tex = {
+    let fallback_tex = with_textures.then(|| {
       let img = mq::Image::gen_image_color(
         16,
         16,
         mq::Color::from_rgba(255, 0, 255, 255),
       );
       mq::Texture2D::from_image(&img)
-    };
+    });
+
+    let mut sprite_sheets = AHashMap::new();
+    for path in all_subpaths(&tex_root, "json")? {
+      let file = fs::read_to_string(tex_root.join(&path))?;
+      let sheet = SpriteSheet::load_aseprite(
+        &file,
+        path.parent().unwrap_or(Path::new("")),
+        &path.display().to_string(),
+      )?;
+
+      let stem: SmolStr = path
+        .with_extension("")
+        .to_string_lossy()
+        .replace('\\', "/")
+        .into();
+      sprite_sheets.insert(stem, sheet);
+    }
 
     let bp_root = [RESOURCES_ROOT, "blueprints"]
       .into_iter()
@@ -64,10 +103,37 @@ impl Resources {
       fabber.load_str(&file, &path.display().to_string())?;
     }
 
+    let tileset_root = [RESOURCES_ROOT, "tilesets"]
+      .into_iter()
+      .collect::<PathBuf>();
+    let mut tilesets = AHashMap::new();
+    for path in all_subpaths(&tileset_root, "kdl")? {
+      let file = fs::read_to_string(tileset_root.join(&path))?;
+      let tileset = Tileset::load_kdl(&file, &path.display().to_string())?;
+
+      let stem: SmolStr = path
+        .with_extension("")
+        .to_string_lossy()
+        .replace('\\', "/")
+        .into();
+      tilesets.insert(stem, tileset);
+    }
+
+    let level_root =
+      [RESOURCES_ROOT, "levels"].into_iter().collect::<PathBuf>();
+    let mut levels = AHashMap::new();
+    for (stem, path) in level_paths(&level_root)? {
+      let level = Level::load(&level_root.join(&path), &tilesets)?;
+      levels.insert(stem, level);
+    }
+
     Ok(Resources {
       textures,
       fallback_tex,
-      fabber,
+      sprite_sheets: Arc::new(sprite_sheets),
+      fabber: Arc::new(fabber),
+      tilesets: Arc::new(tilesets),
+      levels,
     })
   }
 
@@ -78,15 +144,36 @@ impl Resources {
     ResourcesRef(lock)
   }
 
-  pub fn swap(new: Resources) {
+  /// Put the given resources in place, handing back the old ones.
+  pub fn swap(new: Resources) -> Option<Resources> {
     let mut lock = THE_RESOURCES
       .try_lock()
       .expect("assets were mutably borrowed somehow");
-    *lock = Some(new);
+    lock.replace(new)
   }
 
-  pub fn fabber(&self) -> &EntityFab {
-    &self.fabber
+  pub fn fabber(&self) -> Arc<EntityFab> {
+    self.fabber.clone()
+  }
+
+  pub fn tilesets(&self) -> Arc<AHashMap<SmolStr, Tileset>> {
+    self.tilesets.clone()
+  }
+
+  pub fn get_tileset(&self, name: &str) -> Option<&Tileset> {
+    self.tilesets.get(name)
+  }
+
+  pub fn get_level(&self, name: &str) -> Option<&Level> {
+    self.levels.get(name)
+  }
+
+  pub fn sprite_sheets(&self) -> Arc<AHashMap<SmolStr, SpriteSheet>> {
+    self.sprite_sheets.clone()
+  }
+
+  pub fn get_sprite_sheet(&self, name: &str) -> Option<&SpriteSheet> {
+    self.sprite_sheets.get(name)
   }
 
   pub fn get_texture(&self, path: &str) -> mq::Texture2D {
@@ -94,7 +181,8 @@ impl Resources {
       .textures
       .get(path)
       .copied()
-      .unwrap_or(self.fallback_tex)
+      .or(self.fallback_tex)
+      .unwrap_or_else(mq::Texture2D::empty)
   }
 }
 
@@ -103,7 +191,9 @@ impl Drop for Resources {
     for tex in self.textures.values() {
       tex.delete();
     }
-    self.fallback_tex.delete();
+    if let Some(tex) = self.fallback_tex {
+      tex.delete();
+    }
   }
 }
 
@@ -119,6 +209,32 @@ impl Deref for ResourcesRef {
   }
 }
 
+/// Find every level file under the given path, by the name it's loaded as.
+///
+/// Levels are named without their extension, so two files that only differ
+/// by format are an error instead of one silently replacing the other.
+fn level_paths(root: &Path) -> eyre::Result<AHashMap<SmolStr, PathBuf>> {
+  let mut out = AHashMap::new();
+  for ext in ["kdl", "tmj", "tmx"] {
+    for path in all_subpaths(root, ext)? {
+      let stem: SmolStr = path
+        .with_extension("")
+        .to_string_lossy()
+        .replace('\\', "/")
+        .into();
+      if let Some(other) = out.insert(stem.clone(), path.clone()) {
+        eyre::bail!(
+          "levels {} and {} are both named {:?}",
+          other.display(),
+          path.display(),
+          stem
+        );
+      }
+    }
+  }
+  Ok(out)
+}
+
 /// Return all files with the given extension under the given path, as relative paths.
 fn all_subpaths(root: impl AsRef<Path>, ext: &str) -> io::Result<Vec<PathBuf>> {
   if !root.as_ref().is_dir() {
@@ -150,3 +266,20 @@ fn all_subpaths(root: impl AsRef<Path>, ext: &str) -> io::Result<Vec<PathBuf>> {
 
   Ok(out)
 }
+
+#[test]
+fn duplicate_level_names() {
+  let root = std::env::temp_dir()
+    .join(format!("gwair-duplicate-levels-{}", std::process::id()));
+  fs::create_dir_all(root.join("sub")).unwrap();
+  for name in ["start.kdl", "sub/start.tmj", "other.tmx"] {
+    fs::write(root.join(name), "").unwrap();
+  }
+  let paths = level_paths(&root).unwrap();
+  assert_eq!(paths.len(), 3);
+
+  fs::write(root.join("start.tmj"), "").unwrap();
+  let err = level_paths(&root).unwrap_err();
+  fs::remove_dir_all(&root).unwrap();
+  assert!(err.to_string().contains("\"start\""), "{}", err);
+}
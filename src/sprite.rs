@@ -0,0 +1,225 @@
+use std::path::Path;
+
+use ahash::AHashMap;
+use macroquad::prelude as mq;
+use serde::Deserialize;
+use smol_str::SmolStr;
+
+use crate::TICKS_PER_SECOND;
+
+/// A sprite sheet exported from Aseprite, loaded from the `.json` files in
+/// `resources/textures/`.
+///
+/// Export it with "Array" frames and with tags, so the animations come
+/// along. Each tag becomes an animation; tags with a repeat count play that
+/// many times and then hold their last frame, and all the others loop.
+#[derive(Debug)]
+pub struct SpriteSheet {
+  texture: SmolStr,
+  frames: Vec<Frame>,
+  anims: AHashMap<SmolStr, Anim>,
+}
+
+#[derive(Debug)]
+pub struct Frame {
+  /// Where the frame is in the texture.
+  pub source: mq::Rect,
+  /// Where the trimmed frame goes in the untrimmed one.
+  pub offset: mq::Vec2,
+  /// Size of the frame before trimming.
+  pub size: mq::Vec2,
+  /// How long to show the frame for, in ticks.
+  pub duration: u32,
+}
+
+#[derive(Debug)]
+pub struct Anim {
+  /// Frame indices in the order they're played, with the direction already
+  /// taken into account.
+  frames: Vec<usize>,
+  /// How many times to play the animation; `None` loops forever.
+  repeat: Option<u32>,
+}
+
+impl SpriteSheet {
+  /// `dir` is the directory the JSON file is in, relative to the textures
+  /// root, to find the image in. The `filepath` argument is just for error
+  /// reporting purposes.
+  pub fn load_aseprite(
+    src: &str,
+    dir: &Path,
+    filepath: &str,
+  ) -> eyre::Result<SpriteSheet> {
+    #[derive(Deserialize)]
+    struct Raw {
+      frames: Vec<RawFrame>,
+      meta: RawMeta,
+    }
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct RawFrame {
+      frame: RawRect,
+      #[serde(default)]
+      rotated: bool,
+      sprite_source_size: RawRect,
+      source_size: RawSize,
+      duration: u32,
+    }
+    #[derive(Deserialize)]
+    struct RawRect {
+      x: f32,
+      y: f32,
+      w: f32,
+      h: f32,
+    }
+    #[derive(Deserialize)]
+    struct RawSize {
+      w: f32,
+      h: f32,
+    }
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct RawMeta {
+      image: String,
+      #[serde(default)]
+      frame_tags: Vec<RawTag>,
+    }
+    #[derive(Deserialize)]
+    struct RawTag {
+      name: SmolStr,
+      from: usize,
+      to: usize,
+      #[serde(default)]
+      direction: String,
+      repeat: Option<String>,
+    }
+
+    let raw: Raw = serde_json::from_str(src).map_err(|err| {
+      eyre::eyre!(
+        "in sprite sheet {}: {} (frames must be exported as an array)",
+        filepath,
+        err
+      )
+    })?;
+
+    let frames = raw
+      .frames
+      .into_iter()
+      .map(|frame| {
+        if frame.rotated {
+          eyre::bail!("in sprite sheet {}: rotated frames", filepath);
+        }
+        let ticks = frame.duration as f32 / 1000.0 * TICKS_PER_SECOND as f32;
+        Ok(Frame {
+          source: mq::Rect::new(
+            frame.frame.x,
+            frame.frame.y,
+            frame.frame.w,
+            frame.frame.h,
+          ),
+          offset: mq::vec2(
+            frame.sprite_source_size.x,
+            frame.sprite_source_size.y,
+          ),
+          size: mq::vec2(frame.source_size.w, frame.source_size.h),
+          duration: (ticks.round() as u32).max(1),
+        })
+      })
+      .collect::<eyre::Result<Vec<_>>>()?;
+
+    let mut anims = AHashMap::new();
+    for tag in raw.meta.frame_tags {
+      if tag.from > tag.to || tag.to >= frames.len() {
+        eyre::bail!(
+          "in sprite sheet {}: tag {:?} has bad frames {}..={}",
+          filepath,
+          tag.name,
+          tag.from,
+          tag.to
+        );
+      }
+      let forward = (tag.from..=tag.to).collect::<Vec<_>>();
+      // Don't repeat the end frames when bouncing back
+      let bounce = |there: &[usize]| {
+        let back = there.iter().rev().skip(1);
+        let back = back.take(there.len().saturating_sub(2));
+        there.iter().chain(back).copied().collect::<Vec<_>>()
+      };
+      let order = match tag.direction.as_str() {
+        "" | "forward" => forward,
+        "reverse" => forward.into_iter().rev().collect(),
+        "pingpong" => bounce(&forward),
+        "pingpong_reverse" => {
+          bounce(&forward.into_iter().rev().collect::<Vec<_>>())
+        }
+        other => eyre::bail!(
+          "in sprite sheet {}: unknown direction {:?}",
+          filepath,
+          other
+        ),
+      };
+      let repeat = match tag.repeat {
+        None => None,
+        Some(it) => {
+          let count = it.parse::<u32>().map_err(|_| {
+            eyre::eyre!(
+              "in sprite sheet {}: tag {:?} has bad repeat {:?}",
+              filepath,
+              tag.name,
+              it
+            )
+          })?;
+          Some(count).filter(|count| *count > 0)
+        }
+      };
+      anims.insert(
+        tag.name,
+        Anim {
+          frames: order,
+          repeat,
+        },
+      );
+    }
+
+    let texture = dir
+      .join(raw.meta.image)
+      .with_extension("")
+      .to_string_lossy()
+      .replace('\\', "/")
+      .into();
+    Ok(SpriteSheet {
+      texture,
+      frames,
+      anims,
+    })
+  }
+
+  pub fn texture(&self) -> &str {
+    &self.texture
+  }
+
+  pub fn frame(&self, idx: usize) -> &Frame {
+    &self.frames[idx]
+  }
+
+  pub fn anim(&self, name: &str) -> Option<&Anim> {
+    self.anims.get(name)
+  }
+
+  /// The `idx`th frame of the animation, if it has one. Doesn't panic on
+  /// indices from some other version of the sheet, like in an old snapshot.
+  pub fn anim_frame(&self, anim: &str, idx: usize) -> Option<&Frame> {
+    let idx = *self.anim(anim)?.frames().get(idx)?;
+    Some(self.frame(idx))
+  }
+}
+
+impl Anim {
+  pub fn frames(&self) -> &[usize] {
+    &self.frames
+  }
+
+  pub fn repeat(&self) -> Option<u32> {
+    self.repeat
+  }
+}
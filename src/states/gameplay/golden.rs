@@ -0,0 +1,161 @@
+//! Golden-trajectory tests: canned inputs get run through the real game, and
+//! the player's path gets compared against the files in `tests/golden/`.
+//!
+//! They run on `test/golden`, a level of their own with just the floor and
+//! block they run across, so that editing the real levels doesn't change the
+//! paths.
+//!
+//! When a change is supposed to alter how movement feels, run
+//! `UPDATE_GOLDEN=1 cargo test` to write the new paths, and check the diff.
+
+use std::{fmt::Write, fs, path::PathBuf};
+
+use glam::Vec2;
+use palkia::prelude::*;
+
+use super::StateGameplay;
+use crate::{
+  controls::ControlState,
+  ecm::component::{
+    NormalState, PlayerController, PlayerState, Positioned, Velocitized,
+  },
+  replay::InputSource,
+  resources::Resources,
+};
+
+const GOLDEN_LEVEL: &str = "test/golden";
+
+/// Build the controls for each tick from `(ticks, buttons)` steps, where
+/// `buttons` is a space-separated list of `left right up down jump swing`.
+fn script(steps: &[(usize, &str)]) -> Vec<ControlState> {
+  let mut out = Vec::new();
+  for (ticks, buttons) in steps {
+    let mut controls = ControlState::default();
+    let mut movement = Vec2::ZERO;
+    for button in buttons.split_whitespace() {
+      match button {
+        "left" => movement.x -= 1.0,
+        "right" => movement.x += 1.0,
+        "up" => movement.y -= 1.0,
+        "down" => movement.y += 1.0,
+        "jump" => controls.jump = true,
+        "swing" => controls.swing = true,
+        other => panic!("unknown button {:?}", other),
+      }
+    }
+    controls.movement = movement.normalize_or_zero();
+    out.extend(std::iter::repeat_n(controls, *ticks));
+  }
+  out
+}
+
+/// Run the script on the golden level, and write down where the player is
+/// after every tick.
+fn trace(steps: &[(usize, &str)]) -> String {
+  let ticks = script(steps);
+  let tick_count = ticks.len();
+
+  let resources = Resources::load_headless().unwrap();
+  let mut state = StateGameplay::from_resources(
+    &resources,
+    GOLDEN_LEVEL,
+    InputSource::scripted(ticks),
+  )
+  .unwrap();
+  let player = state.player();
+
+  let mut out = String::new();
+  for tick in 0..tick_count {
+    state.on_update().unwrap();
+
+    let world = state.world();
+    let pos = world.query::<&Positioned>(player).unwrap().pos;
+    let vel = world.query::<&Velocitized>(player).unwrap().vel;
+    let controller = world.query::<&PlayerController>(player).unwrap();
+    let player_state = match controller.state() {
+      PlayerState::Normal(normal) => match normal.state {
+        NormalState::OnGround => "on-ground".to_owned(),
+        NormalState::FallingFromLedge { .. } => "falling-from-ledge".to_owned(),
+        NormalState::JumpingUp => "jumping-up".to_owned(),
+        NormalState::Falling => "falling".to_owned(),
+      },
+      PlayerState::Swinging(swinging) => {
+        format!("swinging {:.3}", swinging.angle)
+      }
+      PlayerState::Dead(..) => "dead".to_owned(),
+    };
+    writeln!(
+      out,
+      "{} {} {} {:.3} {:.3} {}",
+      tick, pos.x, pos.y, vel.x, vel.y, player_state
+    )
+    .unwrap();
+  }
+  out
+}
+
+fn check_golden(name: &str, steps: &[(usize, &str)]) {
+  let actual = trace(steps);
+  let path = [env!("CARGO_MANIFEST_DIR"), "tests", "golden"]
+    .into_iter()
+    .collect::<PathBuf>()
+    .join(format!("{}.txt", name));
+
+  if std::env::var_os("UPDATE_GOLDEN").is_some() {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, &actual).unwrap();
+    return;
+  }
+
+  let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+    panic!(
+      "no golden file at {}; run with UPDATE_GOLDEN=1 to make it",
+      path.display()
+    )
+  });
+  if let Some((expected_line, actual_line)) = expected
+    .lines()
+    .zip(actual.lines())
+    .find(|(expected, actual)| expected != actual)
+  {
+    panic!(
+      "{} went off course (tick x y vx vy state):\n  expected {}\n  got      {}",
+      name, expected_line, actual_line
+    );
+  }
+  assert_eq!(
+    expected.lines().count(),
+    actual.lines().count(),
+    "{} has a different number of ticks",
+    name
+  );
+}
+
+#[test]
+fn golden_walk_and_jump() {
+  check_golden(
+    "walk-and-jump",
+    &[(40, ""), (30, "right"), (20, "right jump"), (40, "right")],
+  );
+}
+
+#[test]
+fn golden_short_hop() {
+  check_golden("short-hop", &[(40, ""), (4, "jump"), (50, "")]);
+}
+
+#[test]
+fn golden_turnaround() {
+  check_golden(
+    "turnaround",
+    &[(40, ""), (25, "left"), (25, "right"), (30, "")],
+  );
+}
+
+#[test]
+fn golden_swing() {
+  check_golden(
+    "swing",
+    &[(40, ""), (12, "jump"), (60, "swing right"), (60, "")],
+  );
+}
@@ -1,66 +1,170 @@
+#[cfg(test)]
+mod golden;
+mod rewind;
+mod tuning;
 mod update;
 
-use aglet::CoordVec;
-use broccoli::{aabb::pin::AabbPin, Tree};
-use itertools::Itertools;
-use macroquad::prelude::Color;
+use eyre::WrapErr;
 use palkia::prelude::*;
+use rewind::RewindBuffer;
+use tuning::TuningPanel;
 
 use crate::{
   ecm::{
     self,
-    component::{Collider, ColoredHitbox, HasDims, Mover, Positioned, ZLevel},
+    component::{Positioned, ZLevel},
     message::{MsgDraw, MsgPhysicsTick, MsgTick},
-    resource::{Camera, FabCtxHolder, HitboxTracker, ThePlayerEntity},
+    resource::{
+      Camera, FabCtxHolder, FabberHolder, RespawnPoint, TheControls,
+      ThePlayerEntity, TreeHolder,
+    },
+    snapshot::Snapshot,
   },
   fabctx::FabCtx,
-  geom::{EntityAABB, Hitbox},
+  geom::Hitbox,
   gfx::{GAME_HEIGHT, GAME_WIDTH},
+  replay::{InputSource, Recording},
   resources::Resources,
-  EntityFab,
+  TICKS_PER_SECOND,
 };
 
 pub struct StateGameplay {
   world: World,
+  level_name: String,
+  tuning: TuningPanel,
+
+  input: InputSource,
+  /// Everything that's been pressed since the level started.
+  recording: Recording,
+  rewind: RewindBuffer,
 
-  // TODO: make dt really work
   dt: f32,
 }
 
 impl StateGameplay {
-  pub fn new() -> StateGameplay {
+  pub fn new(
+    level_name: &str,
+    input: InputSource,
+  ) -> eyre::Result<StateGameplay> {
+    Self::from_resources(&Resources::get(), level_name, input)
+  }
+
+  /// Start the level with the given resources instead of the global ones.
+  ///
+  /// Nothing needs a window until it's drawn, so this can run headless with
+  /// [`Resources::load_headless`].
+  pub fn from_resources(
+    resources: &Resources,
+    level_name: &str,
+    input: InputSource,
+  ) -> eyre::Result<StateGameplay> {
     let mut world = World::new();
     ecm::setup_world(&mut world);
 
-    let resources = Resources::get();
     let fabber = resources.fabber();
+    let level = resources
+      .get_level(level_name)
+      .ok_or_else(|| eyre::eyre!("no level named {:?}", level_name))?;
 
-    let ctx = FabCtx {};
+    let ctx = FabCtx::new(resources.tilesets(), resources.sprite_sheets());
 
-    let player = fabber
-      .instantiate(
-        "player",
-        world.spawn().with(Positioned::new(CoordVec::new(0, 0))),
-        &ctx,
-      )
-      .unwrap();
+    let player = fabber.instantiate(
+      "player",
+      world.spawn().with(Positioned::new(level.player_spawn())),
+      &ctx,
+    )?;
     world.insert_resource(ThePlayerEntity(player));
+    world.insert_resource(RespawnPoint(level.player_spawn()));
 
-    setup_temp_level(&mut world, &fabber, &ctx);
+    level.instantiate(&mut world, &fabber, &ctx)?;
     world.insert_resource(FabCtxHolder(ctx));
+    world.insert_resource(FabberHolder(fabber));
+    world.insert_resource(TheControls::default());
 
-    StateGameplay {
+    Ok(StateGameplay {
       world,
-      dt: 1.0 / 60.0,
-    }
+      level_name: level_name.to_owned(),
+      tuning: TuningPanel::default(),
+      input,
+      recording: Recording::new(level_name),
+      rewind: RewindBuffer::default(),
+      dt: 1.0 / TICKS_PER_SECOND as f32,
+    })
+  }
+
+  pub fn level_name(&self) -> &str {
+    &self.level_name
+  }
+
+  pub fn recording(&self) -> &Recording {
+    &self.recording
+  }
+
+  pub fn world(&self) -> &World {
+    &self.world
+  }
+
+  pub fn player(&self) -> Entity {
+    self.world.read_resource::<ThePlayerEntity>().unwrap().0
   }
 
-  pub fn on_update(&mut self) {
+  /// Save the state of the world.
+  pub fn snapshot(&mut self) -> eyre::Result<Snapshot> {
+    Snapshot::take(&mut self.world)
+  }
+
+  /// Put the world back how it was when the snapshot was taken.
+  ///
+  /// This forgets the rewind history, which led up to somewhere else.
+  pub fn restore(&mut self, snapshot: &Snapshot) -> eyre::Result<()> {
+    self.rewind.clear();
+    self.restore_inner(snapshot)
+  }
+
+  fn restore_inner(&mut self, snapshot: &Snapshot) -> eyre::Result<()> {
+    snapshot.restore(&mut self.world)?;
+    update::rebuild_statics(&self.world);
+    update::sync_broadphase(&self.world);
+    Ok(())
+  }
+
+  /// Run one tick of the game.
+  ///
+  /// Errors are from rewinding. A snapshot that can't be restored leaves the
+  /// world as it was, so the game can carry on after them.
+  pub fn on_update(&mut self) -> eyre::Result<()> {
+    for e in self.world.entities() {
+      if let Some(mut pos) = self.world.query::<&mut Positioned>(e) {
+        pos.remember_pos();
+      }
+    }
+
+    let controls = self.input.sample();
+    self.recording.ticks.push(controls);
+    self.world.write_resource::<TheControls>().unwrap().0 = controls;
+
+    // Rewinding goes back a tick instead of going forwards one
+    if controls.rewind {
+      if let Some(snapshot) = self.rewind.pop() {
+        self.restore_inner(&snapshot).wrap_err("couldn't rewind")?;
+      }
+      return Ok(());
+    }
+    // If this fails, the tick still happens
+    let pushed = self
+      .rewind
+      .push(&mut self.world)
+      .wrap_err("couldn't save a rewind snapshot");
+
+    update::rebuild_statics(&self.world);
     self.world.dispatch_to_all(MsgPhysicsTick::new(self.dt));
+    update::do_solids(self);
     update::do_collision(self);
+    update::do_triggers(self);
 
     self.world.dispatch_to_all(MsgTick);
     self.world.finalize();
+    update::sync_broadphase(&self.world);
 
     {
       let player = self.world.read_resource::<ThePlayerEntity>().unwrap().0;
@@ -68,23 +172,21 @@ impl StateGameplay {
       let mut cam = self.world.write_resource::<Camera>().unwrap();
       cam.update(pos);
     }
+    pushed
   }
 
-  pub fn on_draw(&self) {
-    let mut hitboxeds = {
-      let tracker = self.world.read_resource::<HitboxTracker>().unwrap();
-      tracker
-        .iter()
-        .map(|e| {
-          let (pos, dims) =
-            self.world.query::<(&Positioned, &HasDims)>(e).unwrap();
-          EntityAABB::new(e, pos.make_hitbox(*dims))
-        })
-        .collect_vec()
-    };
-    let mut tree = Tree::new(hitboxeds.as_mut_slice());
+  /// Run the debug UI. This happens once per frame, outside of the canvas.
+  pub fn on_ui(&mut self) {
+    self.tuning.ui(&self.world);
+  }
 
-    let camera_center = self.world.read_resource::<Camera>().unwrap().center();
+  /// Draw the game, `alpha` of the way between the last tick and the next.
+  pub fn on_draw(&self, alpha: f32) {
+    let camera_center = self
+      .world
+      .read_resource::<Camera>()
+      .unwrap()
+      .center_at(alpha);
     let view_rect = Hitbox::new(
       camera_center.x,
       camera_center.y,
@@ -93,80 +195,91 @@ impl StateGameplay {
     );
 
     let mut es = Vec::new();
-    // We don't care about the "entity" "doing" the rect scanning...
-    // but we need to give broccoli one, wah
-    tree.find_all_intersect_rect(
-      AabbPin::new(&mut EntityAABB::new(Entity::recompose(0, 0), view_rect)),
-      |_, profile| {
-        let zlevel = self.world.query::<&ZLevel>(profile.e);
-        es.push((profile.e, profile.rect, zlevel.map(|rqr| rqr.level)))
-      },
-    );
+    self
+      .world
+      .write_resource::<TreeHolder>()
+      .unwrap()
+      .for_each_in_box(view_rect, |e, hb| {
+        let zlevel = self.world.query::<&ZLevel>(e);
+        es.push((e, hb, zlevel.map(|rqr| rqr.level)))
+      });
     es.sort_unstable_by(|a, b| {
       ZLevel::sort(a.2, b.2)
         // we want things "above" => less Y to be rendered first, so a
         // cmp b
-        .then(a.1.y.start.total_cmp(&b.1.y.start))
+        .then(a.1.y().cmp(&b.1.y()))
     });
 
     for (e, _, _) in es.iter() {
-      self.world.dispatch(*e, MsgDraw::default());
+      self.world.dispatch(*e, MsgDraw::new(alpha));
     }
   }
 }
 
-fn setup_temp_level(world: &mut World, fab: &EntityFab, ctx: &FabCtx) {
-  const TEMP_LEVEL: &str = r"
-XX                             XXXXXX
-XX                             XXXXXX
-            @
-    
-   XX           
-   XX           
-                           XXXX
-                           
-       XXXX    
-       XXXX          
-                                  XXX
-XXX                               XXX
- 
-
-XXXXXXXXXXXXX              XXXXX
-XXXXXXXXXXXXX              XXXXX
-        
-        
-                      XXX
-        
-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
-        ";
-  for (y, line) in TEMP_LEVEL.lines().enumerate() {
-    for (x, ch) in line.chars().enumerate() {
-      let wx = (x as i32) * 8 - 140;
-      let wy = (y as i32) * 8 - 100;
-      let pos = Positioned::new(CoordVec::new(wx, wy));
-      match ch {
-        'X' => {
-          world
-            .spawn()
-            .with(pos)
-            .with(Mover::new())
-            .with(HasDims::new(8, 8))
-            .with(ColoredHitbox::new(Color::new(
-              (x as f32 + y as f32).sin() * 0.5 + 0.5,
-              0.0,
-              1.0,
-              1.0,
-            )))
-            .with(Collider)
-            .build();
-        }
-        '@' => {
-          fab
-            .instantiate("swingpoint", world.spawn().with(pos), ctx)
-            .unwrap();
-        }
-        _ => {}
-      }
+/// The level the tests build their scenarios in; see
+/// resources/levels/test/sandbox.kdl for the layout.
+#[cfg(test)]
+pub const SANDBOX_LEVEL: &str = "test/sandbox";
+
+#[cfg(test)]
+impl StateGameplay {
+  /// Start the sandbox level headless, playing back the given controls.
+  pub fn sandbox(ticks: Vec<crate::controls::ControlState>) -> StateGameplay {
+    let resources = Resources::load_headless().unwrap();
+    Self::from_resources(
+      &resources,
+      SANDBOX_LEVEL,
+      InputSource::scripted(ticks),
+    )
+    .unwrap()
+  }
+
+  /// Controls that jump, then swing to the right and hold on, for tests
+  /// that need the player in the middle of something.
+  pub fn swing_script() -> Vec<crate::controls::ControlState> {
+    use crate::controls::ControlState;
+
+    let jump = ControlState {
+      jump: true,
+      ..Default::default()
+    };
+    let swing = ControlState {
+      movement: glam::Vec2::X,
+      swing: true,
+      ..Default::default()
+    };
+    let mut ticks = vec![ControlState::default(); 40];
+    ticks.extend(vec![jump; 12]);
+    ticks.extend(vec![swing; 20]);
+    ticks
+  }
+
+  pub fn world_mut(&mut self) -> &mut World {
+    &mut self.world
+  }
+
+  pub fn player_pos(&self) -> aglet::CoordVec {
+    self.world.query::<&Positioned>(self.player()).unwrap().pos
+  }
+
+  /// Run this many ticks.
+  pub fn run(&mut self, ticks: usize) {
+    for _ in 0..ticks {
+      self.on_update().unwrap();
     }
   }
 }
+
+#[test]
+fn headless_walk() {
+  let walk = crate::controls::ControlState {
+    movement: glam::Vec2::X,
+    ..Default::default()
+  };
+  let mut state = StateGameplay::sandbox(vec![walk; 120]);
+
+  let start = state.player_pos();
+  state.run(120);
+  let end = state.player_pos();
+  assert!(end.x > start.x, "{:?} -> {:?}", start, end);
+}
@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+
+use palkia::prelude::*;
+
+use crate::{ecm::snapshot::Snapshot, TICKS_PER_SECOND};
+
+/// How far back rewinding can go, in seconds.
+const REWIND_SECONDS: u32 = 10;
+/// How many ticks apart the snapshots are. Rewinding steps back this many
+/// ticks at a time, so it plays at a quarter of the frame rate.
+const REWIND_INTERVAL: u32 = 4;
+
+/// The last few seconds of the world, for holding the rewind button.
+///
+/// Snapshots save the whole world, tilemaps and all, so there's only one
+/// every [`REWIND_INTERVAL`] ticks. It holds at most [`REWIND_SECONDS`] worth
+/// of them, which is a [`REWIND_INTERVAL`]th of the memory a snapshot every
+/// tick would take.
+#[derive(Debug)]
+pub(super) struct RewindBuffer {
+  snapshots: VecDeque<Snapshot>,
+  capacity: usize,
+  /// Ticks gone forwards since the last snapshot was taken or restored.
+  since_push: u32,
+  /// Ticks spent rewinding since the last snapshot was restored.
+  since_pop: u32,
+}
+
+impl Default for RewindBuffer {
+  fn default() -> Self {
+    let capacity =
+      (REWIND_SECONDS * TICKS_PER_SECOND / REWIND_INTERVAL) as usize;
+    Self {
+      snapshots: VecDeque::with_capacity(capacity),
+      capacity,
+      since_push: 0,
+      since_pop: 0,
+    }
+  }
+}
+
+impl RewindBuffer {
+  /// Call this at the start of every tick that goes forwards. Every
+  /// [`REWIND_INTERVAL`] ticks, it remembers how the world is now, forgetting
+  /// the oldest snapshot if there's no more room.
+  pub fn push(&mut self, world: &mut World) -> eyre::Result<()> {
+    self.since_pop = 0;
+    let due = self.since_push == 0;
+    self.since_push = (self.since_push + 1) % REWIND_INTERVAL;
+    if !due {
+      return Ok(());
+    }
+
+    let snapshot = Snapshot::take(world)?;
+    if self.snapshots.len() == self.capacity {
+      self.snapshots.pop_front();
+    }
+    self.snapshots.push_back(snapshot);
+    Ok(())
+  }
+
+  /// Call this every tick the rewind button is held. Every
+  /// [`REWIND_INTERVAL`] ticks, it takes the most recent snapshot off the
+  /// buffer to go back to.
+  ///
+  /// The oldest one is never handed out, so holding rewind for ages sits
+  /// there instead of running out.
+  pub fn pop(&mut self) -> Option<Snapshot> {
+    let due = self.since_pop == 0;
+    self.since_pop = (self.since_pop + 1) % REWIND_INTERVAL;
+    if !due {
+      return None;
+    }
+
+    // Going forwards from here should save it again
+    self.since_push = 0;
+    if self.snapshots.len() > 1 {
+      self.snapshots.pop_back()
+    } else {
+      self.snapshots.back().cloned()
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.snapshots.clear();
+    self.since_push = 0;
+    self.since_pop = 0;
+  }
+}
+
+#[test]
+fn rewind_undoes_ticks() {
+  use crate::{
+    controls::ControlState,
+    ecm::component::{PlayerController, PlayerState, Velocitized},
+    states::StateGameplay,
+  };
+
+  let rewind = ControlState {
+    rewind: true,
+    ..Default::default()
+  };
+  let mut ticks = StateGameplay::swing_script();
+  let swinging = ticks.len();
+  ticks.extend(vec![ControlState::default(); 30]);
+  ticks.extend(vec![rewind; 30]);
+  let mut state = StateGameplay::sandbox(ticks);
+  // A multiple of `REWIND_INTERVAL`, so there's a snapshot of this tick
+  assert_eq!(swinging as u32 % REWIND_INTERVAL, 0);
+  state.run(swinging);
+
+  let player = state.player();
+  let velocity = |state: &StateGameplay| {
+    state.world().query::<&Velocitized>(player).unwrap().vel
+  };
+  let saved_pos = state.player_pos();
+  let saved_vel = velocity(&state);
+
+  // Let go, fall, then rewind all of that
+  state.run(30);
+  assert_ne!(state.player_pos(), saved_pos);
+  state.run(30);
+
+  assert_eq!(state.player_pos(), saved_pos);
+  assert_eq!(velocity(&state), saved_vel);
+  let controller = state.world().query::<&PlayerController>(player).unwrap();
+  assert!(matches!(controller.state(), PlayerState::Swinging(_)));
+}
+
+#[test]
+fn rewind_interval() {
+  let mut world = World::new();
+  crate::ecm::setup_world(&mut world);
+  let mut buffer = RewindBuffer::default();
+
+  for _ in 0..REWIND_INTERVAL * 3 {
+    buffer.push(&mut world).unwrap();
+  }
+  assert_eq!(buffer.snapshots.len(), 3);
+
+  // The first tick of rewinding goes back, then it waits out the interval
+  assert!(buffer.pop().is_some());
+  for _ in 1..REWIND_INTERVAL {
+    assert!(buffer.pop().is_none());
+  }
+  assert!(buffer.pop().is_some());
+  assert_eq!(buffer.snapshots.len(), 1);
+
+  // Going forwards again saves right away
+  buffer.push(&mut world).unwrap();
+  assert_eq!(buffer.snapshots.len(), 2);
+}
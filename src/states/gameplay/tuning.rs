@@ -0,0 +1,245 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use kdl::{KdlDocument, KdlValue};
+use macroquad::{
+  prelude::*,
+  ui::{hash, root_ui},
+};
+use palkia::prelude::*;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{
+  ecm::{
+    component::{PlayerController, PlayerStats},
+    resource::ThePlayerEntity,
+  },
+  resources::RESOURCES_ROOT,
+};
+
+/// Debug overlay for tweaking the player's [`PlayerStats`] while the game is
+/// running. Press F1 to show or hide it.
+///
+/// Every change becomes an override, so derived values like the jump
+/// velocity follow along with what they're derived from unless they've been
+/// changed themselves. Overridden stats are marked with a `*`. Exporting
+/// writes them into the player blueprint, so they stick after a reload.
+#[derive(Default)]
+pub(super) struct TuningPanel {
+  open: bool,
+  /// What happened on the last export.
+  status: Option<String>,
+}
+
+impl TuningPanel {
+  pub fn ui(&mut self, world: &World) {
+    if is_key_pressed(KeyCode::F1) {
+      self.open = !self.open;
+    }
+    if !self.open {
+      return;
+    }
+
+    let player = world.read_resource::<ThePlayerEntity>().unwrap().0;
+    let Some(mut controller) = world.query::<&mut PlayerController>(player)
+    else {
+      return;
+    };
+
+    let (defaults, current, mut overrides) = match stat_maps(&controller) {
+      Ok(it) => it,
+      Err(err) => {
+        self.status = Some(format!("Couldn't read the stats: {}", err));
+        Default::default()
+      }
+    };
+
+    let mut changed = false;
+    let mut export = false;
+    root_ui().window(
+      hash!(),
+      vec2(8.0, 8.0),
+      vec2(560.0, screen_height() - 16.0),
+      |ui| {
+        if ui.button(None, "Export") {
+          export = true;
+        }
+        ui.same_line(0.0);
+        if ui.button(None, "Reset all") {
+          overrides.clear();
+          changed = true;
+        }
+        if let Some(status) = &self.status {
+          ui.label(None, status);
+        }
+        ui.separator();
+
+        for (key, value) in current.iter() {
+          let label = if overrides.contains_key(key) {
+            format!("{}*", key)
+          } else {
+            key.clone()
+          };
+          let new_value = match value {
+            Value::Bool(it) => {
+              let mut it = *it;
+              ui.checkbox(hash!(key), &label, &mut it);
+              Value::from(it)
+            }
+            Value::Number(num) => {
+              let (Some(old), Some(default)) =
+                (num.as_f64(), defaults.get(key).and_then(Value::as_f64))
+              else {
+                continue;
+              };
+              let (old, default) = (old as f32, default as f32);
+              let max = (default * 4.0).max(old * 2.0).max(1.0);
+              let mut it = old;
+              ui.slider(hash!(key), &label, 0.0..max, &mut it);
+              // The slider's number box rounds to 2 places and writes
+              // that back, so don't count that as a change
+              if format!("{:.2}", it) == format!("{:.2}", old) {
+                continue;
+              }
+              if num.is_f64() {
+                Value::from(it)
+              } else {
+                Value::from(it.round() as i64)
+              }
+            }
+            _ => continue,
+          };
+          if new_value != *value {
+            overrides.insert(key.clone(), new_value);
+            changed = true;
+          }
+        }
+      },
+    );
+
+    if changed {
+      match serde_json::from_value(Value::Object(overrides.clone())) {
+        Ok(it) => controller.set_stat_overrides(it),
+        Err(err) => self.status = Some(format!("Couldn't apply: {}", err)),
+      }
+    }
+    if export {
+      let path = [RESOURCES_ROOT, "blueprints", PLAYER_BLUEPRINT_FILE]
+        .into_iter()
+        .collect::<PathBuf>();
+      self.status = Some(match export_overrides(&overrides, &path) {
+        Ok(()) => format!("Exported to {} (F5 to reload)", path.display()),
+        Err(err) => format!("Couldn't export: {}", err),
+      });
+    }
+  }
+}
+
+/// Stats by the name of their field.
+type StatMap = Map<String, Value>;
+
+/// The blueprint file with the `player` blueprint in it.
+const PLAYER_BLUEPRINT_FILE: &str = "main.kdl";
+
+/// The defaults, the current stats, and the overrides the controller has,
+/// going through serde to get at every field by name.
+fn stat_maps(
+  controller: &PlayerController,
+) -> eyre::Result<(StatMap, StatMap, StatMap)> {
+  let mut overrides = to_map(controller.stat_overrides())?;
+  overrides.retain(|_, value| !value.is_null());
+  Ok((
+    to_map(&PlayerStats::default())?,
+    to_map(controller.stats())?,
+    overrides,
+  ))
+}
+
+fn to_map(it: &impl Serialize) -> eyre::Result<StatMap> {
+  match serde_json::to_value(it)? {
+    Value::Object(map) => Ok(map),
+    other => eyre::bail!("expected the stats to be a map, but got {}", other),
+  }
+}
+
+/// Write the overrides into the blueprint file at `path`, as the `player`
+/// node of the `player` blueprint. Everything else in the file stays as it
+/// was.
+fn export_overrides(overrides: &StatMap, path: &Path) -> eyre::Result<()> {
+  let src = fs::read_to_string(path)?;
+  let out = with_overrides(&src, overrides)?;
+  fs::write(path, out)?;
+  Ok(())
+}
+
+/// The blueprint source with the `player` blueprint's `player` node swapped
+/// for the overrides.
+fn with_overrides(src: &str, overrides: &StatMap) -> eyre::Result<String> {
+  let mut doc: KdlDocument = src.parse()?;
+  let node = doc
+    .get_mut("player")
+    .and_then(|bp| bp.children_mut().as_mut())
+    .and_then(|children| children.get_mut("player"))
+    .ok_or_else(|| eyre::eyre!("no `player` node in the player blueprint"))?;
+
+  node.entries_mut().clear();
+  for (key, value) in overrides {
+    let value = match value {
+      Value::Bool(it) => KdlValue::Bool(*it),
+      Value::Number(num) => match (num.as_i64(), num.as_f64()) {
+        (Some(it), _) => KdlValue::Base10(it),
+        // Go back through f32 so it prints without the float noise
+        (None, Some(it)) => {
+          KdlValue::Base10Float((it as f32).to_string().parse()?)
+        }
+        (None, None) => eyre::bail!("{} is out of range: {}", key, num),
+      },
+      _ => continue,
+    };
+    node.insert(key.replace('_', "-").as_str(), value);
+  }
+  Ok(doc.to_string())
+}
+
+#[test]
+fn export_into_blueprint() {
+  use crate::ecm::component::PlayerStatsOverrides;
+
+  let path = [RESOURCES_ROOT, "blueprints", PLAYER_BLUEPRINT_FILE]
+    .into_iter()
+    .collect::<PathBuf>();
+  let src = fs::read_to_string(path).unwrap();
+  let overrides = [
+    ("jump_height", Value::from(50.5)),
+    ("debugdraw_grab_hbs", Value::from(true)),
+  ]
+  .into_iter()
+  .map(|(key, value)| (key.to_owned(), value))
+  .collect::<Map<_, _>>();
+  let out = with_overrides(&src, &overrides).unwrap();
+
+  // Only the one line changes
+  let changed = src
+    .lines()
+    .zip(out.lines())
+    .filter(|(a, b)| a != b)
+    .collect::<Vec<_>>();
+  assert_eq!(src.lines().count(), out.lines().count());
+  assert_eq!(
+    changed,
+    vec![(
+      "    player",
+      "    player debugdraw-grab-hbs=true jump-height=50.5"
+    )]
+  );
+
+  // And the blueprint reads it back
+  let doc: KdlDocument = out.parse().unwrap();
+  let node = doc.get("player").unwrap().children().unwrap();
+  let read: PlayerStatsOverrides =
+    knurdy::deserialize_node(node.get("player").unwrap()).unwrap();
+  assert_eq!(serde_json::to_value(read).unwrap()["jump_height"], 50.5);
+}
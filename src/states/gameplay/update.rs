@@ -1,8 +1,9 @@
 use super::StateGameplay;
 
+use std::collections::BTreeSet;
+
 use aglet::{CoordVec, Direction8};
 use ahash::AHashMap;
-use broccoli::{aabb::pin::AabbPin, Tree};
 use itertools::Itertools;
 use macroquad::prelude::{self as mq, Vec2};
 use palkia::prelude::*;
@@ -10,88 +11,341 @@ use palkia::prelude::*;
 use crate::{
     ecm::{
         actions,
-        component::{HasDims, Mover, Positioned},
-        message::{MsgRecvHit, MsgSendHit},
+        component::{
+            Actor, AttachedTo, Collider, HasDims, Mover, PathFollower,
+            PlayerController, Positioned, Slope, Trigger,
+        },
+        message::{MsgKill, MsgRecvHit, MsgSendHit, MsgTrigger, TriggerPhase},
         resource::{HitboxTracker, TreeHolder},
     },
-    geom::{EntityAABB, Hitbox},
+    geom::Hitbox,
 };
 
 pub(super) fn do_collision(state: &mut StateGameplay) {
-    let hitboxeds = {
+    sync_broadphase(&state.world);
+
+    let movers = {
         let tracker = state.world.read_resource::<HitboxTracker>().unwrap();
-        tracker
-            .iter()
-            .map(|e| {
-                let (pos, dims) =
-                    state.world.query::<(&Positioned, &HasDims)>(e).unwrap();
-                EntityAABB::new(e, pos.make_hitbox(*dims))
-            })
-            .collect_vec()
+        tracker.movers().collect_vec()
     };
-    let mut hitboxeds_for_tree = hitboxeds.clone();
-    let mut tree = Tree::new(hitboxeds_for_tree.as_mut_slice());
 
     let mut cache = BonkCache::default();
 
-    for profile in hitboxeds.iter() {
-        if let Some(mut mover) = state.world.query::<&mut Mover>(profile.e) {
-            let mut pos = profile.hb().center();
-
-            let bonk_x = do_axis_movement(
-                &state.world,
-                profile.e,
-                mover.remainder,
-                Hitbox::new(pos.x, pos.y, profile.hb().w(), profile.hb().h()),
-                true,
-                &mut tree,
-                &mut cache,
-            );
-            mover.remainder = bonk_x.remainder;
-            pos = bonk_x.new_center;
-
-            let bonk_y = do_axis_movement(
-                &state.world,
-                profile.e,
-                mover.remainder,
-                Hitbox::new(pos.x, pos.y, profile.hb().w(), profile.hb().h()),
-                false,
-                &mut tree,
-                &mut cache,
-            );
-            mover.remainder = bonk_y.remainder;
-            pos = bonk_y.new_center;
+    for e in movers {
+        let (pos, dims) =
+            state.world.query::<(&Positioned, &HasDims)>(e).unwrap();
+        let hb = pos.make_hitbox(*dims);
+        drop(pos);
+        // Not held onto while moving, because what it runs into depends on
+        // its collision layers
+        let remainder = state.world.query::<&Mover>(e).unwrap().remainder;
+        let (jump_nudge, ledge_nudge) = state
+            .world
+            .query::<&PlayerController>(e)
+            .map_or((0, 0), |controller| {
+                let stats = controller.stats();
+                (stats.jump_corner_correction, stats.ledge_corner_correction)
+            });
+        let mut trees = state.world.write_resource::<TreeHolder>().unwrap();
 
-            {
-                let mut pos_comp =
-                    state.world.query::<&mut Positioned>(profile.e).unwrap();
-                pos_comp.pos = pos;
+        let mut pos = hb.center();
+
+        let bonk_x = do_axis_movement(
+            &state.world,
+            e,
+            remainder,
+            Hitbox::new(pos.x, pos.y, hb.w(), hb.h()),
+            true,
+            None,
+            ledge_nudge,
+            &mut trees,
+            &mut cache,
+        );
+        pos = bonk_x.new_center;
+
+        let bonk_y = do_axis_movement(
+            &state.world,
+            e,
+            bonk_x.remainder,
+            Hitbox::new(pos.x, pos.y, hb.w(), hb.h()),
+            false,
+            None,
+            jump_nudge,
+            &mut trees,
+            &mut cache,
+        );
+        pos = bonk_y.new_center;
+
+        trees.update_mover(e, Hitbox::new(pos.x, pos.y, hb.w(), hb.h()));
+        drop(trees);
+        state.world.query::<&mut Mover>(e).unwrap().remainder =
+            bonk_y.remainder;
+        {
+            let mut pos_comp = state.world.query::<&mut Positioned>(e).unwrap();
+            pos_comp.pos = pos;
+        }
+
+        let bonkees = calculate_bonkees(bonk_x, bonk_y);
+        for (bonked, norm) in bonkees {
+            state.world.dispatch(e, MsgSendHit::new(bonked, norm));
+            state.world.dispatch(bonked, MsgRecvHit::new(e, norm));
+        }
+    }
+}
+
+/// Move everything following a path, then everything attached to something.
+/// The solid ones push movers out of the way, and carry the ones riding on
+/// top.
+///
+/// https://maddythorson.medium.com/celeste-and-towerfall-physics-d24bd2ae0fc5
+pub(super) fn do_solids(state: &mut StateGameplay) {
+    sync_broadphase(&state.world);
+
+    let world = &state.world;
+    for e in world.entities() {
+        let Some(mut follower) = world.query::<&mut PathFollower>(e) else {
+            continue;
+        };
+        let pos = world.query::<&Positioned>(e).unwrap().pos;
+        let delta = follower.step(pos, state.dt);
+        drop(follower);
+        move_by(world, e, delta);
+    }
+
+    for e in world.entities() {
+        let Some(attached) = world.query::<&AttachedTo>(e).map(|it| *it) else {
+            continue;
+        };
+        if world.liveness(attached.parent) != EntityLiveness::Alive {
+            continue;
+        }
+        let parent_pos = world.query::<&Positioned>(attached.parent).unwrap();
+        let target = parent_pos.pos + attached.offset;
+        drop(parent_pos);
+        let pos = world.query::<&Positioned>(e).unwrap().pos;
+        move_by(world, e, target - pos);
+    }
+}
+
+/// Move something that isn't an actor, as a moving solid if it's a
+/// [`Collider`].
+fn move_by(world: &World, e: Entity, delta: CoordVec) {
+    if delta == CoordVec::new(0, 0) {
+        return;
+    }
+
+    let dims = world.query::<&HasDims>(e).map(|dims| *dims);
+    match dims {
+        Some(dims) if world.query::<&Collider>(e).is_some() => {
+            move_solid(world, e, dims, delta)
+        }
+        _ => {
+            let mut pos = world.query::<&mut Positioned>(e).unwrap();
+            pos.pos += delta;
+            if let Some(dims) = dims {
+                let mut trees = world.write_resource::<TreeHolder>().unwrap();
+                trees.update_mover(e, pos.make_hitbox(dims));
+            }
+        }
+    }
+}
+
+fn move_solid(world: &World, solid: Entity, dims: HasDims, delta: CoordVec) {
+    let collider = *world.query::<&Collider>(solid).unwrap();
+    let is_actor = |e: Entity| {
+        e != solid
+            && world.query::<&Actor>(e).is_some()
+            && world
+                .query::<&Mover>(e)
+                .is_some_and(|mover| collider.blocks(&mover))
+    };
+    let actor_hitbox = |e: Entity| {
+        let (pos, dims) = world.query::<(&Positioned, &HasDims)>(e).unwrap();
+        pos.make_hitbox(*dims)
+    };
+
+    let mut trees = world.write_resource::<TreeHolder>().unwrap();
+    let hb = world.query::<&Positioned>(solid).unwrap().make_hitbox(dims);
+    // Anything standing right on top gets carried along. Going by touching
+    // isn't good enough, because that lapses between gravity's bonks.
+    let riders = trees
+        .get_entities_in_box(hb.shifted_by(0, -1), is_actor)
+        .into_iter()
+        .filter(|e| actor_hitbox(*e).0.y.end == hb.0.y.start)
+        .collect::<BTreeSet<_>>();
+
+    let mut squished = BTreeSet::new();
+    for (horiz, amount) in [(true, delta.x), (false, delta.y)] {
+        if amount == 0 {
+            continue;
+        }
+        let hb = {
+            let mut pos = world.query::<&mut Positioned>(solid).unwrap();
+            pos.pos = if horiz {
+                pos.pos + CoordVec::new(amount, 0)
+            } else {
+                pos.pos + CoordVec::new(0, amount)
+            };
+            pos.make_hitbox(dims)
+        };
+        trees.update_mover(solid, hb);
+
+        let mut actors = trees
+            .get_entities_in_box(hb, is_actor)
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+        actors.extend(riders.iter().copied());
+        for actor in actors {
+            let actor_hb = actor_hitbox(actor);
+            // One-way solids only shove what's riding them; everything else
+            // passes through
+            let shoves = collider.one_way.is_none() || riders.contains(&actor);
+            if actor_hb.intersects(&hb) && shoves {
+                // Shove it until it's just touching the side that hit it
+                let (solid_span, actor_span) = if horiz {
+                    (hb.0.x, actor_hb.0.x)
+                } else {
+                    (hb.0.y, actor_hb.0.y)
+                };
+                let push = if amount > 0 {
+                    solid_span.end - actor_span.start
+                } else {
+                    solid_span.start - actor_span.end
+                };
+                if !move_actor(world, &mut trees, solid, actor, push, horiz) {
+                    squished.insert(actor);
+                }
+            } else if riders.contains(&actor) {
+                move_actor(world, &mut trees, solid, actor, amount, horiz);
             }
+        }
+    }
+    drop(trees);
 
-            let bonkees = calculate_bonkees(bonk_x, bonk_y);
-            for (bonked, norm) in bonkees {
-                state
-                    .world
-                    .dispatch(profile.e, MsgSendHit::new(bonked, norm));
-                state
-                    .world
-                    .dispatch(bonked, MsgRecvHit::new(profile.e, norm));
+    for actor in squished {
+        world.dispatch(actor, MsgKill);
+    }
+}
+
+/// Move something exactly this many pixels along one axis, unless it runs
+/// into something on the way. Returns whether it got all the way.
+fn move_actor(
+    world: &World,
+    trees: &mut TreeHolder,
+    solid: Entity,
+    actor: Entity,
+    amount: i32,
+    horiz: bool,
+) -> bool {
+    let mut pos = world.query::<&mut Positioned>(actor).unwrap();
+    let dims = *world.query::<&HasDims>(actor).unwrap();
+    let remainder = if horiz {
+        Vec2::new(amount as f32, 0.0)
+    } else {
+        Vec2::new(0.0, amount as f32)
+    };
+    let moved = do_axis_movement(
+        world,
+        actor,
+        remainder,
+        pos.make_hitbox(dims),
+        horiz,
+        Some(solid),
+        0,
+        trees,
+        &mut BonkCache::default(),
+    );
+    pos.pos = moved.new_center;
+    trees.update_mover(actor, pos.make_hitbox(dims));
+    moved.bonk.is_none()
+}
+
+/// Find out what's overlapping each [`Trigger`], and tell the triggers and
+/// the things overlapping them about it.
+pub(super) fn do_triggers(state: &mut StateGameplay) {
+    let world = &state.world;
+    for trigger in world.entities() {
+        let Some(mut trigger_comp) = world.query::<&mut Trigger>(trigger)
+        else {
+            continue;
+        };
+        let Some((pos, dims)) = world.query::<(&Positioned, &HasDims)>(trigger)
+        else {
+            continue;
+        };
+        let hb = pos.make_hitbox(*dims);
+        drop((pos, dims));
+
+        let visitors = world
+            .write_resource::<TreeHolder>()
+            .unwrap()
+            .get_entities_in_box(hb, |e| {
+                e != trigger && world.query::<&Mover>(e).is_some()
+            })
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+        let old_visitors =
+            std::mem::replace(&mut trigger_comp.visitors, visitors.clone());
+        // Let go so the handlers can look at it
+        drop(trigger_comp);
+
+        let exits = old_visitors
+            .difference(&visitors)
+            .map(|e| (*e, TriggerPhase::Exit));
+        let others = visitors.iter().map(|e| {
+            if old_visitors.contains(e) {
+                (*e, TriggerPhase::Stay)
+            } else {
+                (*e, TriggerPhase::Enter)
+            }
+        });
+        for (visitor, phase) in exits.chain(others) {
+            let msg = MsgTrigger::new(trigger, visitor, phase);
+            world.dispatch(trigger, msg);
+            if world.liveness(visitor) == EntityLiveness::Alive {
+                world.dispatch(visitor, msg);
             }
         }
     }
+}
+
+/// Bring the movers in the [`TreeHolder`] up to date with everything
+/// spawned, despawned, or moved since the last time.
+///
+/// Statics are left alone; see [`rebuild_statics`].
+pub(super) fn sync_broadphase(world: &World) {
+    let tracker = world.read_resource::<HitboxTracker>().unwrap();
+    let mut trees = world.write_resource::<TreeHolder>().unwrap();
+    trees.sync_movers(&tracker);
+    for e in tracker.movers() {
+        let (pos, dims) = world.query::<(&Positioned, &HasDims)>(e).unwrap();
+        trees.update_mover(e, pos.make_hitbox(*dims));
+    }
+}
 
-    let data = tree.get_tree_data();
-    drop(tree);
-    state
-        .world
-        .insert_resource(TreeHolder::new(data, hitboxeds_for_tree));
+/// Rebuild the static part of the [`TreeHolder`], if any statics were
+/// spawned or despawned. This happens once at the start of a tick, so
+/// statics spawned partway through one only get run into from the next.
+pub(super) fn rebuild_statics(world: &World) {
+    let tracker = world.read_resource::<HitboxTracker>().unwrap();
+    #[cfg(debug_assertions)]
+    for (e, hb) in tracker.statics() {
+        let (pos, dims) = world.query::<(&Positioned, &HasDims)>(e).unwrap();
+        debug_assert!(
+            pos.make_hitbox(*dims).0 == hb.0,
+            "{:?} moved without a Mover, so the broadphase lost track of it",
+            e
+        );
+    }
+    let mut trees = world.write_resource::<TreeHolder>().unwrap();
+    trees.rebuild_statics(&tracker);
 }
 
 fn calculate_bonkees(
     bonk_x: AxisMove,
     bonk_y: AxisMove,
 ) -> Vec<(Entity, Direction8)> {
-    let bonkees = match (bonk_x.bonk, bonk_y.bonk) {
+    match (bonk_x.bonk, bonk_y.bonk) {
         (Some((e, pos)), None) => {
             vec![(
                 e,
@@ -144,8 +398,7 @@ fn calculate_bonkees(
                 ]
             }
         }
-    };
-    bonkees
+    }
 }
 
 /// The boolean represents "is the normal in the positive direction of this axis?"
@@ -155,47 +408,46 @@ struct AxisMove {
     bonk: Option<(Entity, bool)>,
 }
 
+/// What each mover has already bonked into this tick, and the normal of the
+/// face it hit.
+///
+/// It's keyed by who did the moving, because whether something blocks
+/// depends on that: colliders only stop some layers, and one-way ones only
+/// stop things coming from one side. So A bonking into B says nothing about
+/// B moving into A.
 #[derive(Debug, Default)]
 struct BonkCache(AHashMap<(Entity, Entity), Vec2>);
 
 impl BonkCache {
-    fn sort(a: Entity, b: Entity) -> (Entity, Entity) {
-        if a < b {
-            (a, b)
-        } else {
-            (b, a)
-        }
+    fn insert(&mut self, mover: Entity, other: Entity, norm: Vec2) {
+        self.0.insert((mover, other), norm);
     }
 
-    fn insert(&mut self, a: Entity, b: Entity, norm: Vec2) {
-        self.0.insert(BonkCache::sort(a, b), norm);
-    }
-
-    fn get(&self, a: Entity, b: Entity) -> Option<Vec2> {
-        let sorted = BonkCache::sort(a, b);
-        let norm = *self.0.get(&sorted)?;
-        Some(if sorted.0 == a {
-            // then it was not flipped; we put this in as a bonking b
-            norm
-        } else {
-            -norm
-        })
+    fn get(&self, mover: Entity, other: Entity) -> Option<Vec2> {
+        self.0.get(&(mover, other)).copied()
     }
 }
 
 /// https://maddythorson.medium.com/celeste-and-towerfall-physics-d24bd2ae0fc5
 /// Returns colliders bonked into and the normal of the bonked face.
-fn do_axis_movement<'t>(
+/// `ignore` goes through one collider, for a solid pushing things out of
+/// its own way. `nudge` is how far it can get nudged around a corner that's
+/// in the way; see [`Probe::corner_correction`].
+#[allow(clippy::too_many_arguments)]
+fn do_axis_movement(
     world: &World,
     me: Entity,
     mut remainder: Vec2,
     hb: Hitbox,
     horiz: bool,
-    tree: &mut Tree<'t, EntityAABB>,
+    ignore: Option<Entity>,
+    nudge: i32,
+    trees: &mut TreeHolder,
     cache: &mut BonkCache,
 ) -> AxisMove {
     let delta = (if horiz { remainder.x } else { remainder.y }).round() as i32;
     let mut pos = hb.center();
+    let probe = Probe { world, me, ignore };
 
     if delta != 0 {
         let slot = if horiz {
@@ -209,34 +461,64 @@ fn do_axis_movement<'t>(
         let mut delta = delta;
         while delta != 0 {
             let (dx, dy) = if horiz { (sign, 0) } else { (0, sign) };
-            let proposed_pos = pos + CoordVec::new(dx, dy);
+            let current_aabb = Hitbox::new(pos.x, pos.y, hb.w(), hb.h());
+            let mut proposed_pos = pos + CoordVec::new(dx, dy);
             let proposed_aabb =
                 Hitbox::new(proposed_pos.x, proposed_pos.y, hb.w(), hb.h());
 
             let mut collision_found = None;
-            tree.find_all_intersect_rect(
-                AabbPin::new(&mut (proposed_aabb.0.inner_as(), ())),
-                |_, hit| {
-                    // TODO: is the ability to not shortcut out sooner a problem?
-                    // may be worth pr-ing that
-                    if collision_found.is_some() {
-                        return;
-                    }
-
-                    if let Some(bonk) = cache.get(me, hit.e) {
-                        collision_found = Some((hit.e, bonk));
-                    } else if actions::collides_with(world, me, hit.e) {
-                        collision_found = Some((
-                            hit.e,
-                            if horiz {
-                                mq::vec2(sign as f32, 0.0)
-                            } else {
-                                mq::vec2(0.0, sign as f32)
-                            },
-                        ));
-                    }
-                },
-            );
+            trees.for_each_in_box(proposed_aabb, |hit, _| {
+                // TODO: is the ability to not shortcut out sooner a problem?
+                if collision_found.is_some() || Some(hit) == ignore {
+                    return;
+                }
+
+                if let Some(bonk) = cache.get(me, hit) {
+                    collision_found = Some((hit, bonk));
+                } else if actions::collides_with(
+                    world,
+                    me,
+                    hit,
+                    CoordVec::new(dx, dy),
+                    current_aabb,
+                    proposed_aabb,
+                ) {
+                    collision_found = Some((
+                        hit,
+                        if horiz {
+                            mq::vec2(sign as f32, 0.0)
+                        } else {
+                            mq::vec2(0.0, sign as f32)
+                        },
+                    ));
+                }
+            });
+
+            // Walk up slopes instead of running into them
+            if let Some((other, _)) = collision_found {
+                let raised = proposed_aabb.shifted_by(0, -1);
+                if horiz
+                    && world.query::<&Slope>(other).is_some()
+                    && probe.blocker(trees, current_aabb, raised).is_none()
+                {
+                    proposed_pos.y -= 1;
+                    collision_found = None;
+                }
+            }
+
+            if collision_found.is_some() {
+                if let Some(offset) = probe.corner_correction(
+                    trees,
+                    current_aabb,
+                    proposed_aabb,
+                    sign,
+                    horiz,
+                    nudge,
+                ) {
+                    proposed_pos += offset;
+                    collision_found = None;
+                }
+            }
 
             if let Some((other, norm)) = collision_found {
                 cache.insert(me, other, norm);
@@ -246,8 +528,24 @@ fn do_axis_movement<'t>(
                     bonk: Some((other, sign > 0)),
                 };
             } else {
+                // Unless it's about to go up anyway, something standing on
+                // the ground should stay on it
+                let grounded = horiz
+                    && remainder.y.round() >= 0.0
+                    && probe.is_grounded(trees, current_aabb);
                 pos = proposed_pos;
                 delta -= sign;
+
+                // Follow slopes down instead of walking off them into the air
+                let here = Hitbox::new(pos.x, pos.y, hb.w(), hb.h());
+                if grounded
+                    && !probe.is_grounded(trees, here)
+                    && probe
+                        .blocker(trees, here, here.shifted_by(0, 2))
+                        .is_some_and(|e| world.query::<&Slope>(e).is_some())
+                {
+                    pos.y += 1;
+                }
             }
         }
     }
@@ -258,3 +556,317 @@ fn do_axis_movement<'t>(
         bonk: None,
     }
 }
+
+/// For looking around to see what a mover would run into, without going
+/// through the [`BonkCache`].
+struct Probe<'a> {
+    world: &'a World,
+    me: Entity,
+    /// Goes through this, like in [`do_axis_movement`].
+    ignore: Option<Entity>,
+}
+
+impl Probe<'_> {
+    /// The first thing it would run into going from `prev` to `next`.
+    fn blocker(
+        &self,
+        trees: &mut TreeHolder,
+        prev: Hitbox,
+        next: Hitbox,
+    ) -> Option<Entity> {
+        let motion = next.center() - prev.center();
+        let motion = CoordVec::new(motion.x.signum(), motion.y.signum());
+        let mut found = None;
+        trees.for_each_in_box(next, |hit, _| {
+            if found.is_none()
+                && Some(hit) != self.ignore
+                && actions::collides_with(
+                    self.world, self.me, hit, motion, prev, next,
+                )
+            {
+                found = Some(hit);
+            }
+        });
+        found
+    }
+
+    /// Whether there's something right underneath it.
+    fn is_grounded(&self, trees: &mut TreeHolder, hb: Hitbox) -> bool {
+        self.blocker(trees, hb, hb.shifted_by(0, 1)).is_some()
+    }
+
+    /// If it's only just clipping a corner stepping from `current` to
+    /// `proposed`, how far to nudge it over so it clears it, up to `nudge`
+    /// pixels.
+    ///
+    /// Going up, it gets nudged left or right, so jumps don't stop dead on
+    /// the corner of a ceiling. Going sideways in the air, it gets nudged up,
+    /// onto ledges it only just didn't make it over.
+    fn corner_correction(
+        &self,
+        trees: &mut TreeHolder,
+        current: Hitbox,
+        proposed: Hitbox,
+        sign: i32,
+        horiz: bool,
+        nudge: i32,
+    ) -> Option<CoordVec> {
+        let offsets = if horiz {
+            if self.is_grounded(trees, current) {
+                return None;
+            }
+            (1..=nudge).map(|n| CoordVec::new(0, -n)).collect_vec()
+        } else if sign < 0 {
+            (1..=nudge)
+                .flat_map(|n| [CoordVec::new(-n, 0), CoordVec::new(n, 0)])
+                .collect_vec()
+        } else {
+            return None;
+        };
+
+        offsets.into_iter().find(|offset| {
+            let aside = current.shifted_by(offset.x, offset.y);
+            let past = proposed.shifted_by(offset.x, offset.y);
+            self.blocker(trees, current, aside).is_none()
+                && self.blocker(trees, aside, past).is_none()
+        })
+    }
+}
+
+#[test]
+fn walking_over_slopes() {
+    use crate::{
+        controls::ControlState,
+        ecm::component::{NormalState, PlayerState},
+    };
+
+    let right = ControlState {
+        movement: Vec2::X,
+        ..Default::default()
+    };
+    let left = ControlState {
+        movement: -Vec2::X,
+        ..Default::default()
+    };
+    let mut ticks = vec![ControlState::default(); 10];
+    ticks.extend(vec![right; 40]);
+    ticks.extend(vec![left; 60]);
+    let mut state = StateGameplay::sandbox(ticks);
+    let player = state.player();
+
+    // Flat ground right under the player, up a 45 degree ramp, and flat again
+    let mut solid = |center: CoordVec, w, h, slope: Option<Slope>| {
+        let mut builder = state
+            .world_mut()
+            .spawn()
+            .with(Positioned::new(center))
+            .with(HasDims::new(w, h))
+            .with(Collider::default());
+        if let Some(slope) = slope {
+            builder = builder.with(slope);
+        }
+        builder.build();
+    };
+    let up = Slope {
+        left: 0.0,
+        right: 1.0,
+    };
+    solid(CoordVec::new(-18, 10), 84, 8, None);
+    solid(CoordVec::new(28, 2), 8, 8, Some(up));
+    solid(CoordVec::new(36, 2), 8, 8, None);
+    solid(CoordVec::new(36, -6), 8, 8, Some(up));
+    solid(CoordVec::new(80, -6), 80, 8, None);
+
+    let on_ground = |state: &StateGameplay| {
+        let controller =
+            state.world().query::<&PlayerController>(player).unwrap();
+        matches!(
+            controller.state(),
+            PlayerState::Normal(n) if matches!(n.state, NormalState::OnGround)
+        )
+    };
+
+    state.run(10);
+    assert_eq!(state.player_pos(), CoordVec::new(0, 0));
+
+    // Up...
+    for _ in 0..40 {
+        state.run(1);
+        assert!(
+            on_ground(&state),
+            "left the ground at {:?}",
+            state.player_pos()
+        );
+    }
+    assert!(state.player_pos().x > 44);
+    assert_eq!(state.player_pos().y, -16);
+
+    // ...and back down
+    for _ in 0..60 {
+        state.run(1);
+        assert!(
+            on_ground(&state),
+            "left the ground at {:?}",
+            state.player_pos()
+        );
+    }
+    assert!(state.player_pos().x < 20);
+    assert_eq!(state.player_pos().y, 0);
+}
+
+#[test]
+fn jump_corner_correction() {
+    use crate::controls::ControlState;
+
+    let jump = ControlState {
+        jump: true,
+        ..Default::default()
+    };
+    let mut ticks = vec![ControlState::default(); 10];
+    ticks.extend(vec![jump; 30]);
+    let mut state = StateGameplay::sandbox(ticks);
+
+    // Ground under the player, and a ceiling overhanging the right edge of
+    // their head by 2 pixels
+    let mut solid = |center: CoordVec, w, h| {
+        state
+            .world_mut()
+            .spawn()
+            .with(Positioned::new(center))
+            .with(HasDims::new(w, h))
+            .with(Collider::default())
+            .build();
+    };
+    solid(CoordVec::new(0, 10), 40, 8);
+    solid(CoordVec::new(8, -20), 12, 8);
+
+    state.run(10);
+    assert_eq!(state.player_pos(), CoordVec::new(0, 0));
+
+    let mut highest = state.player_pos();
+    for _ in 0..30 {
+        state.run(1);
+        let pos = state.player_pos();
+        if pos.y < highest.y {
+            highest = pos;
+        }
+    }
+    // Slipped past the corner instead of stopping dead under it
+    assert!(highest.y < -10, "only got up to {:?}", highest);
+    assert_eq!(highest.x, -2);
+}
+
+#[test]
+fn moving_platforms() {
+    use crate::{fabctx::FabCtx, resources::Resources};
+
+    let mut state = StateGameplay::sandbox(Vec::new());
+    let player = state.player();
+    let spawn = state.player_pos();
+    let platform = |state: &mut StateGameplay, from, to| {
+        state
+            .world_mut()
+            .spawn()
+            .with(Positioned::new(from))
+            .with(HasDims::new(16, 8))
+            .with(Collider::default())
+            .with(Mover::new())
+            .with(PathFollower::new(vec![from, to], 30.0))
+            .build()
+    };
+    let pos_of = |state: &StateGameplay, e| {
+        state.world().query::<&Positioned>(e).unwrap().pos
+    };
+
+    // Fall onto one and ride it to the right
+    let under = spawn + CoordVec::new(0, 16);
+    let rideable = platform(&mut state, under, under + CoordVec::new(40, 0));
+    state.run(30);
+    let riding = state.player_pos() - pos_of(&state, rideable);
+    let before = state.player_pos();
+    state.run(30);
+    assert_eq!(state.player_pos() - pos_of(&state, rideable), riding);
+    assert_eq!(state.player_pos(), before + CoordVec::new(15, 0));
+
+    // Get off and onto the floor, then get shoved left by another one
+    state.world_mut().lazy_despawn(rideable);
+    state.world_mut().finalize();
+    state.run(60);
+    let before = state.player_pos();
+    let from = before + CoordVec::new(16, 0);
+    platform(&mut state, from, from + CoordVec::new(-10, 0));
+
+    // A swingpoint is a mover too, but it should stay put as the platform
+    // goes through it
+    let resources = Resources::load_headless().unwrap();
+    let ctx = FabCtx::new(resources.tilesets(), resources.sprite_sheets());
+    let swingpoint_pos = from + CoordVec::new(0, -5);
+    let swingpoint = resources
+        .fabber()
+        .instantiate(
+            "swingpoint",
+            state
+                .world_mut()
+                .spawn()
+                .with(Positioned::new(swingpoint_pos)),
+            &ctx,
+        )
+        .unwrap();
+
+    state.run(30);
+    assert_eq!(state.player_pos(), before + CoordVec::new(-6, 0));
+    assert_eq!(pos_of(&state, swingpoint), swingpoint_pos);
+    assert!(state.world().query::<&PlayerController>(player).is_some());
+}
+
+#[test]
+fn bonk_cache_is_one_way() {
+    let mut world = World::new();
+    let [a, b] = [(); 2].map(|_| world.spawn().build());
+    let mut cache = BonkCache::default();
+
+    cache.insert(a, b, Vec2::X);
+    assert_eq!(cache.get(a, b), Some(Vec2::X));
+    // B might be able to go right through A
+    assert_eq!(cache.get(b, a), None);
+}
+
+#[test]
+fn one_way_moving_platforms() {
+    use aglet::Direction8;
+
+    let mut state = StateGameplay::sandbox(Vec::new());
+    let spawn = state.player_pos();
+    let platform = |state: &mut StateGameplay, from, to| {
+        state
+            .world_mut()
+            .spawn()
+            .with(Positioned::new(from))
+            .with(HasDims::new(16, 8))
+            .with(Collider {
+                one_way: Some(Direction8::North),
+                ..Collider::default()
+            })
+            .with(Mover::new())
+            .with(PathFollower::new(vec![from, to], 30.0))
+            .build()
+    };
+
+    // Riding one works like any other
+    let under = spawn + CoordVec::new(0, 16);
+    let rideable = platform(&mut state, under, under + CoordVec::new(40, 0));
+    state.run(30);
+    let before = state.player_pos();
+    state.run(30);
+    assert_eq!(state.player_pos(), before + CoordVec::new(15, 0));
+
+    // But one coming from the side goes right through
+    state.world_mut().lazy_despawn(rideable);
+    state.world_mut().finalize();
+    state.run(60);
+    let before = state.player_pos();
+    let from = before + CoordVec::new(16, 0);
+    platform(&mut state, from, from + CoordVec::new(-32, 0));
+    state.run(60);
+    assert_eq!(state.player_pos(), before);
+}
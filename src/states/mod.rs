@@ -1,3 +1,5 @@
 mod gameplay;
 
 pub use gameplay::StateGameplay;
+#[cfg(test)]
+pub use gameplay::SANDBOX_LEVEL;
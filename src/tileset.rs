@@ -0,0 +1,226 @@
+use aglet::Direction4;
+use ahash::AHashMap;
+use kdl::{KdlDocument, KdlNode};
+use macroquad::prelude as mq;
+use serde::Deserialize;
+use smol_str::SmolStr;
+
+use crate::ecm::component::Slope;
+
+/// A texture atlas of same-sized tiles, loaded from `resources/tilesets/`.
+///
+/// Tiles are numbered left-to-right, then top-to-bottom, in the order they
+/// appear in the file.
+#[derive(Debug)]
+pub struct Tileset {
+  texture: SmolStr,
+  /// Size of each tile in the texture, which doesn't have to match the size
+  /// it's drawn at.
+  tile_w: u32,
+  tile_h: u32,
+  tiles: Vec<TileInfo>,
+  autotiles: AHashMap<SmolStr, Autotile>,
+  /// Whether autotiles connect to the edges of the map, as if whatever's
+  /// there keeps going past them.
+  autotile_edges_connect: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct TileInfo {
+  name: SmolStr,
+  #[serde(default)]
+  solid: bool,
+  /// Sloped tiles aren't `solid`, because they don't get merged with their
+  /// neighbors.
+  #[serde(default)]
+  slope: Option<Slope>,
+}
+
+/// A 16-tile Wang set, which picks a tile based on which of its four
+/// neighbors it connects to.
+#[derive(Debug)]
+pub struct Autotile {
+  /// Indexed by a bitmask of connected neighbors, in the order of
+  /// [`Direction4::DIRECTIONS`].
+  tiles: [u16; 16],
+}
+
+impl Tileset {
+  /// The `filepath` argument is just for error reporting purposes.
+  pub fn load_kdl(src: &str, filepath: &str) -> eyre::Result<Tileset> {
+    #[derive(Deserialize)]
+    struct Raw {
+      texture: SmolStr,
+      tile_size: (u32, u32),
+      tiles: Vec<TileInfo>,
+      #[serde(default)]
+      autotiles: AHashMap<SmolStr, Vec<SmolStr>>,
+      #[serde(default)]
+      autotile_edges_connect: bool,
+    }
+
+    let doc: KdlDocument = src.parse()?;
+    let mut node = KdlNode::new("tileset");
+    node.set_children(doc);
+    let raw: Raw = knurdy::deserialize_node(&node)
+      .map_err(|err| eyre::eyre!("in tileset {}: {}", filepath, err))?;
+
+    if raw.tiles.len() > u16::MAX as usize {
+      eyre::bail!("in tileset {}: too many tiles", filepath);
+    }
+    let mut tileset = Tileset {
+      texture: raw.texture,
+      tile_w: raw.tile_size.0,
+      tile_h: raw.tile_size.1,
+      tiles: raw.tiles,
+      autotiles: AHashMap::new(),
+      autotile_edges_connect: raw.autotile_edges_connect,
+    };
+
+    for (name, tile_names) in raw.autotiles {
+      if tileset.index_of(&name).is_some() {
+        eyre::bail!(
+          "in tileset {}: autotile {:?} has the same name as a tile",
+          filepath,
+          name
+        );
+      }
+      let Ok(tile_names) = <[SmolStr; 16]>::try_from(tile_names) else {
+        eyre::bail!(
+          "in tileset {}: autotile {:?} needs exactly 16 tiles",
+          filepath,
+          name
+        );
+      };
+      let mut tiles = [0; 16];
+      for (slot, tile_name) in tiles.iter_mut().zip(tile_names.iter()) {
+        *slot = tileset.index_of(tile_name).ok_or_else(|| {
+          eyre::eyre!(
+            "in tileset {}: autotile {:?} uses unknown tile {:?}",
+            filepath,
+            name,
+            tile_name
+          )
+        })?;
+      }
+      tileset.autotiles.insert(name, Autotile { tiles });
+    }
+
+    Ok(tileset)
+  }
+
+  pub fn texture(&self) -> &str {
+    &self.texture
+  }
+
+  pub fn len(&self) -> usize {
+    self.tiles.len()
+  }
+
+  /// Look up a tile by name.
+  ///
+  /// The name of an autotile gives one of its tiles; use
+  /// [`Tilemap::apply_autotiles`](crate::ecm::component::Tilemap::apply_autotiles)
+  /// to pick the right ones.
+  pub fn index_of(&self, name: &str) -> Option<u16> {
+    if let Some(autotile) = self.autotiles.get(name) {
+      return Some(autotile.tiles[0]);
+    }
+    self
+      .tiles
+      .iter()
+      .position(|tile| tile.name == name)
+      .map(|idx| idx as u16)
+  }
+
+  pub fn autotile(&self, name: &str) -> Option<&Autotile> {
+    self.autotiles.get(name)
+  }
+
+  /// The autotile the given tile is part of, if any.
+  pub fn autotile_of(&self, idx: u16) -> Option<&Autotile> {
+    self
+      .autotiles
+      .values()
+      .find(|autotile| autotile.contains(idx))
+  }
+
+  pub fn autotile_edges_connect(&self) -> bool {
+    self.autotile_edges_connect
+  }
+
+  pub fn is_solid(&self, idx: u16) -> bool {
+    self.tiles.get(idx as usize).is_some_and(|tile| tile.solid)
+  }
+
+  pub fn slope(&self, idx: u16) -> Option<Slope> {
+    self.tiles.get(idx as usize).and_then(|tile| tile.slope)
+  }
+
+  /// Where the given tile is in the texture, given how wide the texture is.
+  pub fn source_rect(&self, idx: u16, texture_w: f32) -> mq::Rect {
+    let columns = (texture_w as u32 / self.tile_w).max(1);
+    let idx = idx as u32;
+    mq::Rect::new(
+      ((idx % columns) * self.tile_w) as f32,
+      ((idx / columns) * self.tile_h) as f32,
+      self.tile_w as f32,
+      self.tile_h as f32,
+    )
+  }
+}
+
+impl Autotile {
+  /// Pick the tile to use, given which directions it connects in.
+  pub fn pick(&self, mut connects: impl FnMut(Direction4) -> bool) -> u16 {
+    let mask = Direction4::DIRECTIONS
+      .into_iter()
+      .enumerate()
+      .filter(|(_, dir)| connects(*dir))
+      .fold(0, |mask, (bit, _)| mask | (1 << bit));
+    self.tiles[mask]
+  }
+
+  pub fn contains(&self, idx: u16) -> bool {
+    self.tiles.contains(&idx)
+  }
+}
+
+/// A tileset with one autotile, `wall`, whose tiles are in the same order as
+/// the autotile lists them, so the index of each is its neighbor mask.
+#[cfg(test)]
+pub fn test_autotileset(edges_connect: bool) -> Tileset {
+  use itertools::Itertools;
+
+  let src = format!(
+    "texture \"test\"\n\
+    tile-size 8 8\n\
+    autotile-edges-connect {}\n\
+    tiles {{ {} }}\n\
+    autotiles {{ wall {}; }}",
+    edges_connect,
+    (0..16)
+      .map(|idx| format!("- name=\"w{}\" solid=true;", idx))
+      .join(" "),
+    (0..16).map(|idx| format!("\"w{}\"", idx)).join(" "),
+  );
+  Tileset::load_kdl(&src, "test").unwrap()
+}
+
+#[test]
+fn autotile_mask() {
+  let tileset = test_autotileset(false);
+  let wall = tileset.autotile("wall").unwrap();
+
+  let single = |only: Direction4| wall.pick(|dir| dir == only);
+  assert_eq!(single(Direction4::North), 1);
+  assert_eq!(single(Direction4::East), 2);
+  assert_eq!(single(Direction4::South), 4);
+  assert_eq!(single(Direction4::West), 8);
+
+  // Directions are bitflags in the same order
+  for mask in 0..16 {
+    let picked = wall.pick(|dir| mask & dir as u16 != 0);
+    assert_eq!(picked, mask);
+  }
+}